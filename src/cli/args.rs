@@ -0,0 +1,356 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use hyper::Uri;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::export::ExportFormat;
+use hdl::PageRange;
+
+pub struct ArcWrap<T: ?Sized>(Arc<T>);
+
+impl<T: ?Sized> Clone for ArcWrap<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for ArcWrap<T> {
+    fn as_ref(&self) -> &T {
+        self.0.as_ref()
+    }
+}
+
+impl FromStr for ArcWrap<Uri> {
+    type Err = hyper::http::uri::InvalidUri;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(Arc::from(Uri::try_from(s)?)))
+    }
+}
+
+/// byte count with an optional binary suffix: `512K`, `2M`, `1.5G`
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ByteSize(pub u64);
+
+impl TryFrom<String> for ByteSize {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ByteSize> for String {
+    fn from(size: ByteSize) -> Self {
+        size.to_string()
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let suffix = [('G', 30), ('M', 20), ('K', 10)]
+            .into_iter()
+            .find(|(_, shift)| self.0 >= 1 << shift && self.0.is_multiple_of(1 << shift));
+
+        match suffix {
+            Some((suffix, shift)) => write!(f, "{}{suffix}", self.0 >> shift),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (num, multiplier) = match s.char_indices().last() {
+            Some((i, c)) if c.is_ascii_alphabetic() => {
+                let multiplier = match c.to_ascii_uppercase() {
+                    'B' => 1,
+                    'K' => 1 << 10,
+                    'M' => 1 << 20,
+                    'G' => 1 << 30,
+                    _ => return Err(format!("unknown size suffix: {c:?}")),
+                };
+                (&s[..i], multiplier)
+            }
+            _ => (s, 1),
+        };
+
+        let num: f64 = num
+            .parse()
+            .map_err(|e| format!("invalid size {s:?}: {e}"))?;
+        if !num.is_finite() || num <= 0.0 {
+            return Err(format!("size must be positive: {s:?}"));
+        }
+        // 0 would mean no limit at all
+        let bytes = (num * multiplier as f64) as u64;
+        if bytes == 0 {
+            return Err(format!("size must be at least 1 byte: {s:?}"));
+        }
+
+        Ok(Self(bytes))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub(crate) enum ProgressFormat {
+    /// `bar` if stderr is a terminal, `plain` otherwise
+    #[default]
+    Auto,
+    /// progress bars on stderr
+    Bar,
+    /// one line per page on stderr
+    Plain,
+    /// newline-delimited json events on stdout or `--progress-fd`
+    Json,
+}
+
+/// `Name: value` of `--header`
+#[derive(Clone)]
+pub struct Header(pub String, pub String);
+
+impl FromStr for Header {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                Ok(Self(name.trim().to_owned(), value.trim().to_owned()))
+            }
+            _ => Err(format!("expected `Name: value`, got {s:?}")),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// inspect the config file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand)]
+pub(crate) enum ConfigCommand {
+    /// print the config in effect: command line, environment, config file and defaults
+    Show,
+}
+
+// settings missing here come from the environment, then from the config file
+#[derive(Parser)]
+#[command(
+    subcommand_negates_reqs = true,
+    override_usage = "hdl [OPTIONS] [URLS]...\n       hdl [OPTIONS] config show"
+)]
+pub(crate) struct Args {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
+    /// parallel jobs count [default: 3]
+    #[arg(short, long, env = "HDL_JOBS")]
+    pub(crate) jobs: Option<usize>,
+
+    /// [default: ./out/]
+    #[arg(short, long, env = "HDL_OUT_DIR")]
+    pub(crate) out_dir: Option<PathBuf>,
+
+    /// limit total download rate (bytes per second, e.g. 512K, 2M);
+    /// SIGUSR1 halves and SIGUSR2 doubles it while running
+    #[arg(long, value_name = "RATE", env = "HDL_LIMIT_RATE")]
+    pub(crate) limit_rate: Option<ByteSize>,
+
+    /// number of galleries downloaded at the same time (images share `--jobs`) [default: 1]
+    #[arg(short, long, env = "HDL_GALLERIES")]
+    pub(crate) galleries: Option<usize>,
+
+    /// gallery directory inside `--out-dir`: `{title}`, `{site}` and `{id}` are replaced
+    /// and `/` makes subdirectories [default: {title}]
+    #[arg(long, value_name = "TEMPLATE", env = "HDL_DIR_TEMPLATE")]
+    pub(crate) dir_template: Option<String>,
+
+    /// attempts per page [default: 3]
+    #[arg(long, env = "HDL_RETRIES")]
+    pub(crate) retries: Option<usize>,
+
+    /// seconds to wait for a response or the next chunk of it [default: 10]
+    #[arg(long, value_name = "SECS", env = "HDL_TIMEOUT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) timeout: Option<u64>,
+
+    /// http proxy, https is tunneled with CONNECT
+    #[arg(long, value_name = "URL", env = "HDL_PROXY")]
+    pub(crate) proxy: Option<String>,
+
+    /// value of the `Cookie` header, e.g. `name=value; other=value`
+    #[arg(long, env = "HDL_COOKIES")]
+    pub(crate) cookies: Option<String>,
+
+    /// extra request header, can be repeated
+    #[arg(long = "header", value_name = "NAME: VALUE")]
+    pub(crate) headers: Vec<Header>,
+
+    /// config file [default: $XDG_CONFIG_HOME/hdl/config.toml]
+    #[arg(long, value_name = "FILE", env = "HDL_CONFIG")]
+    pub(crate) config: Option<PathBuf>,
+
+    /// read gallery urls from file: one per line, blank lines and `#` comments are skipped
+    #[arg(short, long, value_name = "FILE")]
+    pub(crate) input_file: Option<PathBuf>,
+
+    /// download only these pages, e.g. `1-10,15,20-`; files keep the gallery page numbers
+    #[arg(short, long, value_name = "RANGES")]
+    pub(crate) pages: Option<PageRange>,
+
+    /// resolve image urls and print them to stdout instead of downloading,
+    /// the download history is neither read nor written
+    #[arg(short = 'n', long)]
+    pub(crate) dry_run: bool,
+
+    /// output format of `--dry-run`
+    #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+    pub(crate) format: ExportFormat,
+
+    /// download galleries again even if the history says they are complete,
+    /// and overwrite already downloaded images
+    #[arg(short, long)]
+    pub(crate) force: bool,
+
+    /// download at most this many galleries of each search, tag, artist or group url
+    #[arg(long, value_name = "N")]
+    pub(crate) max_galleries: Option<usize>,
+
+    /// read at most this many pages of each search, tag, artist or group url
+    #[arg(long, value_name = "N")]
+    pub(crate) listing_pages: Option<usize>,
+
+    /// keep only galleries in this language from search, tag, artist and group urls,
+    /// e.g. `english`; galleries whose listing doesn't tell are left out
+    #[arg(long, value_name = "LANG")]
+    pub(crate) language: Option<String>,
+
+    /// download the newest revision of galleries the site lists newer versions of (e-hentai);
+    /// older copies in the history are marked as superseded
+    #[arg(long)]
+    pub(crate) latest: bool,
+
+    /// how progress is reported
+    #[arg(long, value_enum, default_value_t)]
+    pub(crate) progress: ProgressFormat,
+
+    /// print only errors and the final summary
+    #[arg(short, long, conflicts_with = "progress")]
+    pub(crate) quiet: bool,
+
+    /// log to stderr, warnings by default: `-v` info, `-vv` debug, `-vvv` trace
+    #[arg(short, long, action = ArgAction::Count)]
+    pub(crate) verbose: u8,
+
+    /// append the log to this file, at least at info level
+    #[arg(long, value_name = "FILE")]
+    pub(crate) log_file: Option<PathBuf>,
+
+    /// write the failed galleries and pages as json, see `--retry-failed`
+    #[arg(long, value_name = "FILE")]
+    pub(crate) failed_report: Option<PathBuf>,
+
+    /// download again what failed according to a `--failed-report` file
+    #[arg(long, value_name = "FILE", conflicts_with = "pages")]
+    pub(crate) retry_failed: Option<PathBuf>,
+
+    /// write `--progress json` to this file descriptor instead of stdout
+    #[arg(long, value_name = "FD")]
+    pub(crate) progress_fd: Option<u32>,
+
+    /// record every request and response to this file as json lines, see `--replay`
+    #[arg(long, value_name = "FILE")]
+    pub(crate) record: Option<PathBuf>,
+
+    /// keep whole images in `--record` instead of only their size
+    #[arg(long, requires = "record")]
+    pub(crate) record_images: bool,
+
+    /// serve the run from a `--record` file instead of the network,
+    /// the download history is neither read nor written
+    #[arg(long, value_name = "FILE", conflicts_with = "record")]
+    pub(crate) replay: Option<PathBuf>,
+
+    /// save pages that fail to parse to this directory, with their url and the error
+    #[arg(long, value_name = "DIR")]
+    pub(crate) debug_dump: Option<PathBuf>,
+
+    #[arg(required_unless_present_any = ["input_file", "retry_failed"])]
+    pub(crate) urls: Vec<ArcWrap<Uri>>,
+}
+
+impl Args {
+    /// urls from the command line followed by the ones from `--input-file`
+    pub(crate) fn gallery_urls(&self) -> anyhow::Result<Vec<String>> {
+        use anyhow::Context;
+
+        let mut urls = self
+            .urls
+            .iter()
+            .map(|url| url.as_ref().to_string())
+            .collect::<Vec<_>>();
+
+        if let Some(path) = &self.input_file {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read input file: {path:?}"))?;
+
+            for (i, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                Uri::try_from(line)
+                    .with_context(|| format!("invalid url at {path:?}:{}", i + 1))?;
+                urls.push(line.to_owned());
+            }
+        }
+
+        Ok(urls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_byte_sizes() {
+        let size = |s: &str| s.parse::<ByteSize>().map(|size| size.0);
+
+        assert_eq!(size("512"), Ok(512));
+        assert_eq!(size("2M"), Ok(2 << 20));
+        assert_eq!(size("1.5k"), Ok(1536));
+        assert_eq!(size(" 1G "), Ok(1 << 30));
+        assert_eq!(size("10b"), Ok(10));
+
+        for invalid in ["0", "-1", "0.5", "0.0001K", "", "fast", "2X", "inf", "NaN"] {
+            assert!(size(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn prints_byte_sizes_back() {
+        for size in ["512", "2M", "1536", "3G"] {
+            assert_eq!(size.parse::<ByteSize>().unwrap().to_string(), size);
+        }
+        assert_eq!("1.5k".parse::<ByteSize>().unwrap().to_string(), "1536");
+    }
+
+    #[test]
+    fn rejects_a_zero_timeout() {
+        let url = "https://nhentai.net/g/123456/";
+        let timeout = |secs: &str| Args::try_parse_from(["hdl", "--timeout", secs, url]);
+
+        assert_eq!(timeout("30").unwrap().timeout, Some(30));
+        assert!(timeout("0").is_err());
+    }
+}
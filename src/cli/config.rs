@@ -0,0 +1,17 @@
+use std::time::Duration;
+
+pub(crate) const PROGRESS_BAR_TICK_TIME: Duration = Duration::from_millis(100);
+
+/// how often `--progress json` reports the bytes of running pages
+pub(crate) const JSON_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// how often `--progress plain` prints the overall progress
+pub(crate) const PLAIN_SUMMARY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// time pages in flight get to finish after Ctrl-C before they are aborted
+pub(crate) const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// defaults of the settings missing from the command line, the environment and the config file
+pub(crate) const JOBS: usize = 3;
+pub(crate) const GALLERIES: usize = 1;
+pub(crate) const OUT_DIR: &str = "./out/";
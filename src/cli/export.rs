@@ -0,0 +1,118 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use hdl::ResolvedPage;
+use serde::Serialize;
+
+use crate::cli::summary::GalleryReport;
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// gallery metadata followed by `<page> <url>` lines
+    #[default]
+    Text,
+    /// one json object per gallery and per page
+    Jsonl,
+    /// `aria2c --input-file` with `dir=` and `out=` options
+    Aria2,
+    /// `wget --input-file`, bare urls since wget can't name the files
+    Wget,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonLine<'a> {
+    Gallery {
+        url: &'a str,
+        site: &'static str,
+        title: Option<&'a str>,
+        pages: usize,
+    },
+    Page {
+        gallery: &'a str,
+        index: usize,
+        url: &'a str,
+        out: &'a PathBuf,
+    },
+}
+
+/// print the result of `--dry-run` for one gallery to stdout
+pub(crate) fn print(
+    format: ExportFormat,
+    site: &'static str,
+    report: &GalleryReport,
+    pages: &[ResolvedPage],
+) -> Result<()> {
+    let mut out = Vec::new();
+
+    match format {
+        ExportFormat::Text => {
+            writeln!(out, "url: {}", report.url)?;
+            writeln!(out, "site: {site}")?;
+            if let Some(title) = &report.title {
+                writeln!(out, "title: {title}")?;
+            }
+            writeln!(out, "pages: {}", report.pages)?;
+            for page in pages {
+                writeln!(out, "{:4} {}", page.page, page.url)?;
+            }
+            writeln!(out)?;
+        }
+        ExportFormat::Jsonl => {
+            let gallery = &report.url;
+
+            serde_json::to_writer(
+                &mut out,
+                &JsonLine::Gallery {
+                    url: gallery,
+                    site,
+                    title: report.title.as_deref(),
+                    pages: report.pages,
+                },
+            )?;
+            writeln!(out)?;
+
+            for page in pages {
+                serde_json::to_writer(
+                    &mut out,
+                    &JsonLine::Page {
+                        gallery,
+                        index: page.page,
+                        url: &page.url,
+                        out: &page.out,
+                    },
+                )?;
+                writeln!(out)?;
+            }
+        }
+        ExportFormat::Aria2 => {
+            writeln!(out, "# {site} {}", report.url)?;
+            if let Some(title) = &report.title {
+                writeln!(out, "# {title}")?;
+            }
+            for page in pages {
+                writeln!(out, "{}", page.url)?;
+                if let Some(dir) = page.out.parent() {
+                    writeln!(out, "  dir={}", dir.display())?;
+                }
+                if let Some(name) = page.out.file_name() {
+                    writeln!(out, "  out={}", name.to_string_lossy())?;
+                }
+            }
+        }
+        ExportFormat::Wget => {
+            for page in pages {
+                writeln!(out, "{}", page.url)?;
+            }
+        }
+    }
+
+    // galleries are printed as a whole so concurrent ones don't interleave
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&out)?;
+    stdout.flush()?;
+
+    Ok(())
+}
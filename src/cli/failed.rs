@@ -0,0 +1,207 @@
+//! `--failed-report` writes the failures of a run, `--retry-failed` downloads them again;
+//! an interrupted run writes the same format to [`resume_path`]
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use hdl::PageRange;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::history;
+use crate::cli::summary::{GalleryReport, Outcome};
+use crate::Job;
+
+#[derive(Serialize, Deserialize)]
+struct Report {
+    galleries: Vec<FailedGallery>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FailedGallery {
+    url: String,
+    site: Option<String>,
+    title: Option<String>,
+    out_dir: Option<PathBuf>,
+    /// `--pages` of the run, `None` for every page
+    selection: Option<String>,
+    /// error that stopped the gallery, its pages are all retried
+    error: Option<String>,
+    pages: Vec<FailedPage>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FailedPage {
+    page: usize,
+    url: String,
+    error: String,
+}
+
+/// galleries without failures are left out
+pub(crate) fn write(path: &Path, reports: &[(Job, GalleryReport)]) -> Result<()> {
+    let galleries = reports
+        .iter()
+        .filter(|(_, report)| {
+            matches!(
+                report.outcome(),
+                Outcome::Failed | Outcome::PartiallySucceeded
+            )
+        })
+        .map(|(job, report)| FailedGallery {
+            url: report.url.clone(),
+            site: report.site.map(str::to_owned),
+            title: report.title.clone(),
+            out_dir: report.out_dir.clone(),
+            selection: job.selection.as_ref().map(PageRange::to_string),
+            // pages that were never scheduled aren't known, so the whole selection is retried
+            error: match &report.error {
+                Some(e) => Some(format!("{e:#}")),
+                None if report.interrupted => Some("interrupted".to_owned()),
+                None => None,
+            },
+            pages: report
+                .failed
+                .iter()
+                .map(|page| FailedPage {
+                    page: page.page,
+                    url: page.url.clone(),
+                    error: format!("{:#}", page.error),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let json = serde_json::to_string_pretty(&Report { galleries })?;
+    std::fs::write(path, json + "\n")
+        .with_context(|| format!("failed to write failure report: {path:?}"))
+}
+
+/// next to the download history
+pub(crate) fn resume_path() -> Option<PathBuf> {
+    history::default_path().map(|path| path.with_file_name("resume.json"))
+}
+
+/// jobs downloading only what failed in the run that wrote `path`
+pub(crate) fn read(path: &Path) -> Result<Vec<Job>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read failure report: {path:?}"))?;
+    let report: Report = serde_json::from_str(&content)
+        .with_context(|| format!("invalid failure report: {path:?}"))?;
+
+    report
+        .galleries
+        .into_iter()
+        .map(|gallery| {
+            let selection = gallery
+                .selection
+                .map(|s| s.parse::<PageRange>())
+                .transpose()
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("invalid page selection of {}", gallery.url))?;
+
+            let pages = if gallery.error.is_some() || gallery.pages.is_empty() {
+                selection.clone().unwrap_or_default()
+            } else {
+                gallery.pages.iter().map(|page| page.page).collect()
+            };
+
+            Ok(Job {
+                url: gallery.url,
+                pages,
+                selection,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use hdl::{ErrorKind, FailedPage};
+
+    use super::*;
+
+    fn job(url: &str, selection: Option<&str>) -> Job {
+        let selection = selection.map(|s| s.parse::<PageRange>().unwrap());
+        Job {
+            url: url.to_owned(),
+            pages: selection.clone().unwrap_or_default(),
+            selection,
+        }
+    }
+
+    fn report(url: &str, pages: usize, failed: &[usize]) -> GalleryReport {
+        let mut report = GalleryReport::new(url.to_owned());
+        report.pages = pages;
+        report.failed = failed
+            .iter()
+            .map(|&page| {
+                let error = hdl::Error::msg(ErrorKind::Network, "connection reset");
+                FailedPage::new(page, format!("{url}{page}/"), error)
+            })
+            .collect();
+        report
+    }
+
+    /// writes `reports` to the file `name` and reads them back as (url, pages, selection)
+    fn round_trip(
+        name: &str,
+        reports: &[(Job, GalleryReport)],
+    ) -> Vec<(String, String, Option<String>)> {
+        let name = format!("hdl-{name}-{}.json", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        write(&path, reports).unwrap();
+        let jobs = read(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        jobs.unwrap()
+            .into_iter()
+            .map(|job| {
+                let selection = job.selection.as_ref().map(PageRange::to_string);
+                (job.url, job.pages.to_string(), selection)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn retries_the_failed_pages_of_partial_galleries() {
+        let url = "https://nhentai.net/g/123456/";
+        let jobs = round_trip(
+            "partial",
+            &[(job(url, Some("1-10")), report(url, 10, &[2, 4]))],
+        );
+        assert_eq!(
+            jobs,
+            [(url.to_owned(), "2,4".to_owned(), Some("1-10".to_owned()))]
+        );
+    }
+
+    #[test]
+    fn retries_the_whole_selection_of_stopped_galleries() {
+        let stopped = "https://nhentai.net/g/1/";
+        let mut stopped_report = report(stopped, 3, &[3]);
+        stopped_report.error = Some(anyhow!("gallery failed").context(ErrorKind::RateLimited));
+
+        let interrupted = "https://nhentai.net/g/2/";
+        let mut interrupted_report = report(interrupted, 0, &[]);
+        interrupted_report.interrupted = true;
+
+        let done = "https://nhentai.net/g/3/";
+
+        let jobs = round_trip(
+            "stopped",
+            &[
+                (job(stopped, Some("3-")), stopped_report),
+                (job(interrupted, None), interrupted_report),
+                (job(done, None), report(done, 5, &[])),
+            ],
+        );
+        assert_eq!(
+            jobs,
+            [
+                (stopped.to_owned(), "3-".to_owned(), Some("3-".to_owned())),
+                // every page
+                (interrupted.to_owned(), String::new(), None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Status {
+    Complete,
+    Partial,
+    Failed,
+}
+
+/// one line of the history file, the last line for a `(site, gallery_id)` wins
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Record {
+    pub site: String,
+    pub gallery_id: String,
+    pub url: String,
+    pub status: Status,
+    pub out_dir: Option<PathBuf>,
+    pub pages: usize,
+    /// unix timestamp in seconds
+    pub time: u64,
+    /// url of the newer revision downloaded in place of this gallery, see `--latest`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<String>,
+}
+
+/// append-only download history in JSON lines
+pub(crate) struct History {
+    entries: HashMap<(String, String), Record>,
+    file: Mutex<File>,
+}
+
+/// `$XDG_DATA_HOME/hdl/history.jsonl` or `~/.local/share/hdl/history.jsonl`
+pub(crate) fn default_path() -> Option<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+
+    Some(data_home.join("hdl").join("history.jsonl"))
+}
+
+#[inline]
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl History {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create history dir: {dir:?}"))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open history file: {path:?}"))?;
+
+        let mut entries = HashMap::new();
+        let mut content = String::new();
+        BufReader::new(&file)
+            .read_to_string(&mut content)
+            .with_context(|| format!("failed to read history file: {path:?}"))?;
+        // a run killed while appending leaves the last line cut short
+        let complete = content.is_empty() || content.ends_with('\n');
+        let lines = content.lines().collect::<Vec<_>>();
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record = match serde_json::from_str::<Record>(line) {
+                Ok(record) => record,
+                Err(e) if !complete && i + 1 == lines.len() => {
+                    warn!(line = i + 1, error = %e, "ignoring truncated history record");
+                    break;
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("corrupted history record at {path:?}:{}", i + 1))
+                }
+            };
+            entries.insert((record.site.clone(), record.gallery_id.clone()), record);
+        }
+        // the next record doesn't continue the broken one
+        if !complete {
+            let len = content.rfind('\n').map_or(0, |i| i + 1);
+            file.set_len(len as u64)
+                .with_context(|| format!("failed to truncate history file: {path:?}"))?;
+        }
+
+        Ok(Self {
+            entries,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub(crate) fn get(&self, site: &str, gallery_id: &str) -> Option<&Record> {
+        self.entries.get(&(site.to_owned(), gallery_id.to_owned()))
+    }
+
+    pub(crate) fn append(&self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| anyhow::anyhow!("history file lock is poisoned"))?;
+        file.write_all(&line)
+            .context("failed to write history record")?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// removed before and after the test
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "hdl-history-{name}-{}/history.jsonl",
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    fn record(gallery_id: &str, status: Status, pages: usize) -> Record {
+        Record {
+            site: "nhentai".to_owned(),
+            gallery_id: gallery_id.to_owned(),
+            url: format!("https://nhentai.net/g/{gallery_id}/"),
+            status,
+            out_dir: Some(PathBuf::from("out/Sample Gallery")),
+            pages,
+            time: 1_700_000_000,
+            superseded_by: None,
+        }
+    }
+
+    #[test]
+    fn reloads_the_last_record_of_each_gallery() {
+        let file = TempFile::new("reload");
+
+        let history = History::open(&file.0).unwrap();
+        assert!(history.get("nhentai", "123456").is_none());
+        history
+            .append(&record("123456", Status::Partial, 3))
+            .unwrap();
+        history
+            .append(&record("654321", Status::Failed, 0))
+            .unwrap();
+        history
+            .append(&record("123456", Status::Complete, 24))
+            .unwrap();
+        drop(history);
+
+        let history = History::open(&file.0).unwrap();
+        let latest = history.get("nhentai", "123456").unwrap();
+        assert_eq!(latest.status, Status::Complete);
+        assert_eq!(latest.pages, 24);
+        assert_eq!(
+            history.get("nhentai", "654321").unwrap().status,
+            Status::Failed
+        );
+        // galleries are told apart by site too
+        assert!(history.get("ehentai", "123456").is_none());
+    }
+
+    #[test]
+    fn ignores_a_truncated_last_line() {
+        let file = TempFile::new("truncated");
+        let history = History::open(&file.0).unwrap();
+        history
+            .append(&record("123456", Status::Complete, 24))
+            .unwrap();
+        drop(history);
+
+        let line = serde_json::to_string(&record("654321", Status::Complete, 10)).unwrap();
+        let mut content = fs::read_to_string(&file.0).unwrap();
+        content.push_str(&line[..line.len() / 2]);
+        fs::write(&file.0, content).unwrap();
+
+        let history = History::open(&file.0).unwrap();
+        assert_eq!(
+            history.get("nhentai", "123456").unwrap().status,
+            Status::Complete
+        );
+        assert!(history.get("nhentai", "654321").is_none());
+
+        // later records aren't glued to the broken line
+        history
+            .append(&record("654321", Status::Partial, 4))
+            .unwrap();
+        drop(history);
+        let history = History::open(&file.0).unwrap();
+        assert_eq!(
+            history.get("nhentai", "654321").unwrap().status,
+            Status::Partial
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_records_before_the_end() {
+        let file = TempFile::new("corrupted");
+        fs::create_dir_all(file.0.parent().unwrap()).unwrap();
+        let line = serde_json::to_string(&record("123456", Status::Complete, 24)).unwrap();
+        fs::write(&file.0, format!("{{\"site\":\n{line}\n")).unwrap();
+
+        let error = History::open(&file.0).err().unwrap();
+        assert!(
+            error.to_string().starts_with("corrupted history record at"),
+            "{error:#}"
+        );
+    }
+}
@@ -0,0 +1,533 @@
+//! `--progress json`: one json object per line, the schema is documented in the readme
+//! and only ever gains fields
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use hdl::{ErrorKind, Event, EventKind, GalleryId, Subscription};
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::cli::config;
+
+#[derive(Serialize)]
+struct Line<'a> {
+    /// unix time in milliseconds
+    time: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gallery: Option<u64>,
+    #[serde(flatten)]
+    event: JsonEvent<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JsonEvent<'a> {
+    GalleryStarted {
+        url: &'a str,
+        site: &'static str,
+    },
+    Metadata {
+        title: &'a str,
+        out_dir: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        redirected_from: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title_original: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        category: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        uploader: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        posted: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rating: Option<f32>,
+        #[serde(skip_serializing_if = "<[String]>::is_empty")]
+        tags: &'a [String],
+    },
+    PageCount {
+        pages: usize,
+    },
+    PageQueued {
+        page: usize,
+        url: &'a str,
+    },
+    PageResolving {
+        page: usize,
+    },
+    PageStarted {
+        page: usize,
+        file_name: &'a Path,
+    },
+    PageSize {
+        page: usize,
+        bytes: u64,
+    },
+    PageProgress {
+        page: usize,
+        bytes: u64,
+    },
+    PageRetry {
+        page: usize,
+        attempt: usize,
+        kind: &'static str,
+        error: &'a str,
+    },
+    PageFailed {
+        page: usize,
+        kind: &'static str,
+        error: &'a str,
+    },
+    PageDone {
+        page: usize,
+        bytes: u64,
+    },
+    GalleryFinished {
+        pages: usize,
+        done: usize,
+        failed: usize,
+        retries: usize,
+        bytes: u64,
+        elapsed_ms: u64,
+        error_kind: Option<&'static str>,
+        error: Option<&'a str>,
+    },
+    RunFinished {
+        galleries: usize,
+        pages: usize,
+        done: usize,
+        failed: usize,
+        bytes: u64,
+        elapsed_ms: u64,
+    },
+}
+
+struct Gallery {
+    started: Instant,
+    done: usize,
+    failed: usize,
+    retries: usize,
+    bytes: u64,
+    /// bytes of the running pages, `true` if they changed since the last report
+    pages: BTreeMap<usize, (u64, bool)>,
+}
+
+#[derive(Default)]
+struct Totals {
+    galleries: usize,
+    pages: usize,
+    done: usize,
+    failed: usize,
+    bytes: u64,
+}
+
+struct Writer<W> {
+    out: W,
+}
+
+impl<W: Write> Writer<W> {
+    fn line(&mut self, gallery: Option<GalleryId>, event: JsonEvent) -> Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+
+        serde_json::to_writer(
+            &mut self.out,
+            &Line {
+                time,
+                gallery: gallery.map(GalleryId::get),
+                event,
+            },
+        )?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// the json lines of the client events, fed one event at a time
+struct Progress<W> {
+    out: Writer<W>,
+    started: Instant,
+    galleries: HashMap<GalleryId, Gallery>,
+    totals: Totals,
+}
+
+impl<W: Write> Progress<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out: Writer { out },
+            started: Instant::now(),
+            galleries: HashMap::new(),
+            totals: Totals::default(),
+        }
+    }
+
+    /// `page_progress` of the pages that received bytes since the last call
+    fn progress(&mut self) -> Result<()> {
+        for (&id, gallery) in &mut self.galleries {
+            for (&page, (bytes, changed)) in &mut gallery.pages {
+                if std::mem::take(changed) {
+                    let json = JsonEvent::PageProgress {
+                        page,
+                        bytes: *bytes,
+                    };
+                    self.out.line(Some(id), json)?;
+                }
+            }
+        }
+        self.out.out.flush()?;
+        Ok(())
+    }
+
+    fn event(&mut self, event: &Event) -> Result<()> {
+        let galleries = &mut self.galleries;
+        let totals = &mut self.totals;
+
+        let id = event.gallery;
+        let json = match &event.kind {
+            EventKind::GalleryDiscovered { url, site } => {
+                galleries.insert(
+                    id,
+                    Gallery {
+                        started: Instant::now(),
+                        done: 0,
+                        failed: 0,
+                        retries: 0,
+                        bytes: 0,
+                        pages: BTreeMap::new(),
+                    },
+                );
+                JsonEvent::GalleryStarted { url, site }
+            }
+            EventKind::Metadata(metadata) => {
+                let details = &metadata.details;
+                JsonEvent::Metadata {
+                    title: &metadata.title,
+                    out_dir: &metadata.out_dir,
+                    redirected_from: metadata.redirected_from.as_deref(),
+                    title_original: details.title_original.as_deref(),
+                    category: details.category.as_deref(),
+                    uploader: details.uploader.as_deref(),
+                    posted: details.posted,
+                    size: details.size,
+                    rating: details.rating,
+                    tags: &details.tags,
+                }
+            }
+            EventKind::PageCount { pages } => JsonEvent::PageCount { pages: *pages },
+            EventKind::PageQueued { page, url } => JsonEvent::PageQueued { page: *page, url },
+            EventKind::PageResolving { page } => JsonEvent::PageResolving { page: *page },
+            EventKind::PageStarted { page, file_name } => {
+                if let Some(gallery) = galleries.get_mut(&id) {
+                    gallery.pages.insert(*page, (0, false));
+                }
+                JsonEvent::PageStarted {
+                    page: *page,
+                    file_name,
+                }
+            }
+            EventKind::PageSize { page, bytes } => JsonEvent::PageSize {
+                page: *page,
+                bytes: *bytes,
+            },
+            EventKind::BytesReceived { page, len } => {
+                if let Some(gallery) = galleries.get_mut(&id) {
+                    gallery.bytes += *len as u64;
+                    let (bytes, changed) = gallery.pages.entry(*page).or_default();
+                    *bytes += *len as u64;
+                    *changed = true;
+                }
+                return Ok(());
+            }
+            EventKind::PageRetry {
+                page,
+                attempt,
+                kind,
+                error,
+            } => {
+                if let Some(gallery) = galleries.get_mut(&id) {
+                    gallery.retries += 1;
+                    // the next attempt starts from scratch
+                    if let Some((bytes, _)) = gallery.pages.remove(page) {
+                        gallery.bytes -= bytes;
+                    }
+                }
+                JsonEvent::PageRetry {
+                    page: *page,
+                    attempt: *attempt,
+                    kind: kind.name(),
+                    error,
+                }
+            }
+            EventKind::PageFailed { page, kind, error } => {
+                if let Some(gallery) = galleries.get_mut(&id) {
+                    gallery.failed += 1;
+                    if let Some((bytes, _)) = gallery.pages.remove(page) {
+                        gallery.bytes -= bytes;
+                    }
+                }
+                JsonEvent::PageFailed {
+                    page: *page,
+                    kind: kind.name(),
+                    error,
+                }
+            }
+            EventKind::PageDone { page } => {
+                let bytes = galleries.get_mut(&id).map_or(0, |gallery| {
+                    gallery.done += 1;
+                    gallery.pages.remove(page).map_or(0, |(bytes, _)| bytes)
+                });
+                JsonEvent::PageDone { page: *page, bytes }
+            }
+            EventKind::GalleryFinished {
+                pages,
+                failed,
+                error_kind,
+                error,
+            } => {
+                let gallery = galleries.remove(&id);
+                let (done, retries, bytes, elapsed) = gallery.map_or((0, 0, 0, 0), |g| {
+                    (
+                        g.done,
+                        g.retries,
+                        g.bytes,
+                        g.started.elapsed().as_millis() as u64,
+                    )
+                });
+
+                totals.galleries += 1;
+                totals.pages += pages;
+                totals.done += done;
+                totals.failed += failed;
+                totals.bytes += bytes;
+
+                JsonEvent::GalleryFinished {
+                    pages: *pages,
+                    done,
+                    failed: *failed,
+                    retries,
+                    bytes,
+                    elapsed_ms: elapsed,
+                    error_kind: error_kind.as_ref().map(ErrorKind::name),
+                    error: error.as_deref(),
+                }
+            }
+            _ => return Ok(()),
+        };
+
+        self.out.line(Some(id), json)?;
+        self.out.out.flush()?;
+        Ok(())
+    }
+
+    /// `run_finished` with the totals of the galleries
+    fn finish(&mut self) -> Result<()> {
+        let totals = &self.totals;
+        self.out.line(
+            None,
+            JsonEvent::RunFinished {
+                galleries: totals.galleries,
+                pages: totals.pages,
+                done: totals.done,
+                failed: totals.failed,
+                bytes: totals.bytes,
+                elapsed_ms: self.started.elapsed().as_millis() as u64,
+            },
+        )?;
+        self.out.out.flush()?;
+        Ok(())
+    }
+}
+
+/// writes the client events to `out` until `quit` fires, then the totals of the run
+pub async fn json_progress<W: Write>(
+    mut events: Subscription,
+    out: W,
+    mut quit: oneshot::Receiver<()>,
+) -> Result<()> {
+    let mut progress = Progress::new(out);
+    let mut tick = tokio::time::interval(config::JSON_PROGRESS_INTERVAL);
+
+    loop {
+        let event = tokio::select! {
+            // events already sent are written before quitting
+            biased;
+            _ = tick.tick() => {
+                progress.progress()?;
+                continue;
+            }
+            event = events.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = &mut quit => break,
+        };
+
+        progress.event(&event)?;
+    }
+
+    progress.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::cli::test_run::{self, OutDir};
+
+    /// the lines of the test run, with `page_progress` after every chunk
+    async fn lines(out: &OutDir) -> Vec<Value> {
+        let mut progress = Progress::new(Vec::new());
+        for event in test_run::events(out).await {
+            progress.event(&event).unwrap();
+            if let EventKind::BytesReceived { .. } = event.kind {
+                progress.progress().unwrap();
+            }
+        }
+        progress.finish().unwrap();
+
+        String::from_utf8(progress.out.out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn keys(line: &Value) -> Vec<&str> {
+        let mut keys = line
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[tokio::test]
+    async fn writes_the_documented_fields() {
+        let out = OutDir::new("json");
+        let lines = lines(&out).await;
+
+        let common = ["event", "gallery", "time"];
+        let schema: &[(&str, &[&str])] = &[
+            ("gallery_started", &["site", "url"]),
+            ("page_count", &["pages"]),
+            ("page_queued", &["page", "url"]),
+            ("page_resolving", &["page"]),
+            ("page_started", &["file_name", "page"]),
+            ("page_size", &["bytes", "page"]),
+            ("page_progress", &["bytes", "page"]),
+            ("page_retry", &["attempt", "error", "kind", "page"]),
+            ("page_failed", &["error", "kind", "page"]),
+            ("page_done", &["bytes", "page"]),
+            (
+                "gallery_finished",
+                &[
+                    "bytes",
+                    "done",
+                    "elapsed_ms",
+                    "error",
+                    "error_kind",
+                    "failed",
+                    "pages",
+                    "retries",
+                ],
+            ),
+        ];
+        for (event, fields) in schema {
+            let line = lines
+                .iter()
+                .find(|line| line["event"] == *event)
+                .unwrap_or_else(|| panic!("no {event} line"));
+            let mut expected = common.iter().chain(*fields).copied().collect::<Vec<_>>();
+            expected.sort_unstable();
+            assert_eq!(keys(line), expected, "{line}");
+        }
+
+        // the optional fields are left out, the details of the site api filled in
+        let metadata = lines
+            .iter()
+            .filter(|line| line["event"] == "metadata")
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys(metadata[0]),
+            ["event", "gallery", "out_dir", "time", "title"]
+        );
+        assert_eq!(
+            keys(metadata[1]),
+            [
+                "category",
+                "event",
+                "gallery",
+                "out_dir",
+                "posted",
+                "rating",
+                "redirected_from",
+                "size",
+                "tags",
+                "time",
+                "title",
+                "title_original",
+                "uploader",
+            ]
+        );
+        assert_eq!(metadata[1]["redirected_from"], test_run::OLD);
+        assert_eq!(metadata[1]["gallery"], 2);
+
+        let retry = lines
+            .iter()
+            .find(|line| line["event"] == "page_retry")
+            .unwrap();
+        assert_eq!((&retry["page"], &retry["attempt"]), (&2.into(), &1.into()));
+        assert_eq!(retry["kind"], "other");
+        let failed = lines
+            .iter()
+            .find(|line| line["event"] == "page_failed")
+            .unwrap();
+        assert_eq!(
+            (&failed["page"], &failed["kind"]),
+            (&3.into(), &"not_found".into())
+        );
+
+        let finished = lines
+            .iter()
+            .filter(|line| line["event"] == "gallery_finished")
+            .collect::<Vec<_>>();
+        assert_eq!(finished[0]["error"], Value::Null);
+        assert_eq!(finished[0]["retries"], 1);
+        assert_eq!(finished[1]["error_kind"], "not_found");
+
+        let run = lines.last().unwrap();
+        assert_eq!(
+            keys(run),
+            [
+                "bytes",
+                "done",
+                "elapsed_ms",
+                "event",
+                "failed",
+                "galleries",
+                "pages",
+                "time"
+            ]
+        );
+        assert_eq!(run["event"], "run_finished");
+        assert_eq!(
+            [
+                &run["galleries"],
+                &run["pages"],
+                &run["done"],
+                &run["failed"],
+                &run["bytes"]
+            ],
+            [3, 4, 3, 1, 19].map(Value::from).each_ref()
+        );
+    }
+}
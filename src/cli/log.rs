@@ -0,0 +1,105 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use indicatif::MultiProgress;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+/// buffers one log line and prints it with the progress bars hidden
+struct SuspendWriter {
+    progress: MultiProgress,
+    buf: Vec<u8>,
+}
+
+impl Write for SuspendWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SuspendWriter {
+    fn drop(&mut self) {
+        self.progress.suspend(|| {
+            let _ = io::stderr().write_all(&self.buf);
+        });
+    }
+}
+
+/// span fields are cached per formatter type, a separate type keeps the
+/// colors of stderr out of the log file
+struct FileFields(fmt::format::DefaultFields);
+
+impl<'writer> fmt::FormatFields<'writer> for FileFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        writer: Writer<'writer>,
+        fields: R,
+    ) -> std::fmt::Result {
+        self.0.format_fields(writer, fields)
+    }
+}
+
+fn level(verbose: u8, quiet: bool) -> LevelFilter {
+    match verbose {
+        0 if quiet => LevelFilter::ERROR,
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// stderr gets warnings above the progress bars, errors only with `-q` and more with `-v`;
+/// the log file gets at least `info`
+pub(crate) fn init(
+    verbose: u8,
+    quiet: bool,
+    log_file: Option<&Path>,
+    progress: MultiProgress,
+) -> Result<()> {
+    let stderr = fmt::layer()
+        .with_writer(move || SuspendWriter {
+            progress: progress.clone(),
+            buf: Vec::new(),
+        })
+        .with_filter(Targets::new().with_target("hdl", level(verbose, quiet)));
+
+    let file = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open log file: {path:?}"))?;
+            let level = level(verbose, quiet).max(LevelFilter::INFO);
+
+            Some(
+                fmt::layer()
+                    .with_ansi(false)
+                    .fmt_fields(FileFields(fmt::format::DefaultFields::new()))
+                    .with_writer(Mutex::new(file))
+                    .with_filter(Targets::new().with_target("hdl", level)),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .try_init()
+        .context("failed to set up logging")
+}
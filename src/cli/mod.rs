@@ -0,0 +1,13 @@
+pub(crate) mod args;
+pub(crate) mod config;
+pub(crate) mod export;
+pub(crate) mod failed;
+pub(crate) mod history;
+pub(crate) mod json;
+pub(crate) mod log;
+pub(crate) mod plain;
+pub(crate) mod progress;
+pub(crate) mod settings;
+pub(crate) mod summary;
+#[cfg(test)]
+pub(crate) mod test_run;
@@ -0,0 +1,296 @@
+//! `--progress plain`: one line per page on stderr, for logs and terminals that can't redraw
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use anyhow::Result;
+use hdl::{Event, EventKind, GalleryId, Subscription};
+use indicatif::{HumanBytes, HumanDuration};
+use tokio::sync::oneshot;
+
+use crate::cli::config;
+
+struct Gallery {
+    /// title once known, the url before that
+    label: String,
+    started: Instant,
+    /// pages the gallery will queue, when the site tells up front
+    count: Option<usize>,
+    queued: usize,
+    done: usize,
+    bytes: u64,
+    /// bytes of the running pages
+    pages: HashMap<usize, u64>,
+}
+
+impl Gallery {
+    /// pages to download as far as known
+    fn total(&self) -> usize {
+        self.count.unwrap_or(0).max(self.queued)
+    }
+}
+
+/// the lines of `--progress plain`, fed one event at a time
+struct Printer {
+    /// keep only the errors
+    quiet: bool,
+    galleries: HashMap<GalleryId, Gallery>,
+    /// pages done at the last summary, nothing is printed if it didn't change
+    last_done: Option<usize>,
+}
+
+impl Printer {
+    fn new(quiet: bool) -> Self {
+        Self {
+            quiet,
+            galleries: HashMap::new(),
+            last_done: None,
+        }
+    }
+
+    /// the progress of the running galleries, `None` if it didn't change
+    fn summary(&mut self) -> Option<String> {
+        let galleries = &self.galleries;
+        let done = galleries.values().map(|g| g.done).sum::<usize>();
+        if self.quiet || galleries.is_empty() || self.last_done == Some(done) {
+            return None;
+        }
+        self.last_done = Some(done);
+
+        let queued = galleries.values().map(Gallery::total).sum::<usize>();
+        let bytes = galleries.values().map(|g| g.bytes).sum::<u64>();
+        Some(format!(
+            "progress: {} galleries running, {done}/{queued} pages, {}",
+            galleries.len(),
+            HumanBytes(bytes)
+        ))
+    }
+
+    /// the lines `event` prints, if any
+    fn event(&mut self, event: Event) -> Vec<String> {
+        let quiet = self.quiet;
+        let mut lines = Vec::new();
+
+        let id = event.gallery;
+        if let EventKind::GalleryDiscovered { url, site } = &event.kind {
+            if !quiet {
+                lines.push(format!("{url}: started ({site})"));
+            }
+            self.galleries.insert(
+                id,
+                Gallery {
+                    label: url.clone(),
+                    started: Instant::now(),
+                    count: None,
+                    queued: 0,
+                    done: 0,
+                    bytes: 0,
+                    pages: HashMap::new(),
+                },
+            );
+            return lines;
+        }
+        let Some(gallery) = self.galleries.get_mut(&id) else {
+            return lines;
+        };
+        let label = gallery.label.clone();
+
+        match event.kind {
+            EventKind::Metadata(metadata) => {
+                if !quiet {
+                    lines.push(format!(
+                        "{label}: {:?} in {:?}",
+                        metadata.title, metadata.out_dir
+                    ));
+                    if let Some(old) = &metadata.redirected_from {
+                        lines.push(format!("{label}: newer version of {old}"));
+                    }
+                }
+                gallery.label = metadata.title;
+            }
+            EventKind::PageCount { pages } => gallery.count = Some(pages),
+            EventKind::PageQueued { .. } => gallery.queued += 1,
+            EventKind::BytesReceived { page, len } => {
+                *gallery.pages.entry(page).or_default() += len as u64;
+                gallery.bytes += len as u64;
+            }
+            EventKind::PageDone { page } => {
+                gallery.done += 1;
+                let bytes = gallery.pages.remove(&page).unwrap_or_default();
+                if !quiet {
+                    lines.push(format!(
+                        "{label}: page {page} done ({}) [{}/{}]",
+                        HumanBytes(bytes),
+                        gallery.done,
+                        gallery.total()
+                    ));
+                }
+            }
+            EventKind::PageRetry {
+                page,
+                attempt,
+                error,
+                ..
+            } => {
+                gallery.pages.remove(&page);
+                if !quiet {
+                    lines.push(format!(
+                        "{label}: page {page} attempt {attempt} failed, retrying: {error}"
+                    ));
+                }
+            }
+            EventKind::PageFailed { page, error, .. } => {
+                gallery.pages.remove(&page);
+                lines.push(format!("{label}: page {page} failed: {error}"));
+            }
+            EventKind::GalleryFinished {
+                pages,
+                failed,
+                error,
+                ..
+            } => {
+                // quiet leaves gallery errors to the final summary
+                match error {
+                    _ if quiet => {}
+                    Some(error) => lines.push(format!("{label}: failed: {error}")),
+                    None => lines.push(format!(
+                        "{label}: finished {}/{pages} pages, {failed} failed, {} in {}",
+                        gallery.done,
+                        HumanBytes(gallery.bytes),
+                        HumanDuration(gallery.started.elapsed())
+                    )),
+                }
+                self.galleries.remove(&id);
+            }
+            _ => {}
+        }
+
+        lines
+    }
+}
+
+/// prints the client events until `quit` fires; `quiet` keeps only the errors
+pub async fn plain_progress(
+    mut events: Subscription,
+    quiet: bool,
+    mut quit: oneshot::Receiver<()>,
+) -> Result<()> {
+    let mut printer = Printer::new(quiet);
+
+    let mut summary = tokio::time::interval(config::PLAIN_SUMMARY_INTERVAL);
+    summary.tick().await;
+
+    loop {
+        let event = tokio::select! {
+            // events already sent are printed before quitting
+            biased;
+            _ = summary.tick() => {
+                if let Some(line) = printer.summary() {
+                    eprintln!("{line}");
+                }
+                continue;
+            }
+            event = events.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = &mut quit => break,
+        };
+
+        for line in printer.event(event) {
+            eprintln!("{line}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::test_run::{self, OutDir, EHENTAI, GALLERY, OLD, REMOVED};
+
+    const TITLE: &str = "[Example Circle] Sample Gallery [English]";
+
+    /// the lines of the test run, with the output directory as `<out>`
+    async fn lines(name: &str, quiet: bool) -> Vec<String> {
+        let out = OutDir::new(name);
+        let mut printer = Printer::new(quiet);
+        let mut lines = Vec::new();
+        for event in test_run::events(&out).await {
+            lines.extend(printer.event(event));
+        }
+
+        let dir = out.0.to_str().unwrap();
+        lines
+            .into_iter()
+            .map(|line| line.replace(dir, "<out>"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn prints_pages_and_galleries() {
+        let page_3 = "https://i3.nhentai.net/galleries/987654/3.jpg";
+        let expected = [
+            format!("{GALLERY}: started (Nhentai)"),
+            format!("{GALLERY}: {TITLE:?} in \"<out>/{TITLE}\""),
+            format!("{TITLE}: page 1 done (7 B) [1/3]"),
+            format!(
+                "{TITLE}: page 2 attempt 1 failed, retrying: failed to download \
+                 https://i3.nhentai.net/galleries/987654/2.jpg (page 2): connection reset"
+            ),
+            format!("{TITLE}: page 2 done (7 B) [2/3]"),
+            format!(
+                "{TITLE}: page 3 failed: download failed after 1 attempts: failed to download \
+                 {page_3} (page 3): failed to get {page_3}: not found: status code: 404 Not Found"
+            ),
+            format!("{TITLE}: finished 2/3 pages, 1 failed, 14 B in 0 seconds"),
+            format!("{REMOVED}: started (Nhentai)"),
+            format!(
+                "{REMOVED}: failed: error happend in downloader task: failed to get {REMOVED}: \
+                 not found: status code: 404 Not Found"
+            ),
+            // the download of a superseded gallery is the newest version
+            format!("{EHENTAI}: started (Ehentai)"),
+            format!("{EHENTAI}: {TITLE:?} in \"<out>/ehentai/{TITLE}\""),
+            format!("{EHENTAI}: newer version of {OLD}"),
+            format!("{TITLE}: page 2 done (5 B) [1/1]"),
+            format!("{TITLE}: finished 1/1 pages, 0 failed, 5 B in 0 seconds"),
+        ];
+
+        assert_eq!(lines("plain", false).await, expected);
+    }
+
+    #[tokio::test]
+    async fn quiet_prints_only_failed_pages() {
+        let lines = lines("quiet", true).await;
+
+        assert_eq!(lines.len(), 1, "{lines:#?}");
+        assert!(lines[0].starts_with(&format!("{TITLE}: page 3 failed: ")));
+    }
+
+    #[tokio::test]
+    async fn summarizes_running_galleries_when_they_progress() {
+        let out = OutDir::new("summary");
+        let mut printer = Printer::new(false);
+        let mut quiet = Printer::new(true);
+        assert_eq!(printer.summary(), None);
+
+        let mut events = test_run::events(&out).await.into_iter();
+        for event in events.by_ref() {
+            let done = matches!(event.kind, EventKind::PageDone { .. });
+            quiet.event(event.clone());
+            printer.event(event);
+            if done {
+                break;
+            }
+        }
+        assert_eq!(
+            printer.summary().as_deref(),
+            Some("progress: 1 galleries running, 1/3 pages, 7 B")
+        );
+        // nothing new
+        assert_eq!(printer.summary(), None);
+        assert_eq!(quiet.summary(), None);
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use hdl::{EventKind, GalleryId, Subscription};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use tokio::sync::oneshot;
+
+use crate::cli::config;
+
+/// bytes received recently, for a throughput that follows rate changes quickly
+#[derive(Default)]
+struct Throughput {
+    total: u64,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Throughput {
+    const WINDOW: Duration = Duration::from_secs(3);
+
+    fn add(&mut self, len: usize) {
+        self.total += len as u64;
+    }
+
+    fn sample(&mut self, now: Instant) {
+        self.samples.push_back((now, self.total));
+        while self
+            .samples
+            .front()
+            .is_some_and(|&(time, _)| now - time > Self::WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// bytes per second
+    fn rate(&self) -> u64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(&(start, from)), Some(&(end, to))) if end > start => {
+                ((to - from) as f64 / (end - start).as_secs_f64()) as u64
+            }
+            _ => 0,
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!("{} {}/s", HumanBytes(self.total), HumanBytes(self.rate()))
+    }
+}
+
+struct Gallery {
+    bar: ProgressBar,
+    /// the bar length came from [`EventKind::PageCount`], queued pages don't add to it
+    counted: bool,
+    bytes: Throughput,
+    failed: usize,
+    retried: usize,
+}
+
+impl Gallery {
+    fn stats(&self) -> String {
+        let mut stats = self.bytes.summary();
+        if self.failed > 0 {
+            stats.push_str(&format!(", {} failed", self.failed));
+        }
+        if self.retried > 0 {
+            stats.push_str(&format!(", {} retried", self.retried));
+        }
+        stats
+    }
+}
+
+#[inline]
+fn total_message(client: &hdl::Client) -> String {
+    match client.rate_limit() {
+        Some(rate) => format!("galleries [limit {}/s]", HumanBytes(rate)),
+        None => "galleries".to_owned(),
+    }
+}
+
+/// draws the client events until `quit` fires
+pub async fn progress_bar(
+    mut events: Subscription,
+    client: hdl::Client,
+    progress: MultiProgress,
+    mut quit: oneshot::Receiver<()>,
+) -> Result<()> {
+    let total_progress = {
+        let style = ProgressStyle::with_template("{msg}: {wide_bar} {pos}/{len} {prefix}")?;
+        ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::hidden())
+            .with_style(style)
+            .with_message(total_message(&client))
+    };
+    let limited = client.rate_limit().is_some();
+    let mut total_shown = false;
+    let mut total_bytes = Throughput::default();
+
+    let main_style =
+        ProgressStyle::with_template("{msg}: {wide_bar} {pos}/{len} {prefix} eta {eta}")?;
+    let page_style = ProgressStyle::with_template("{spinner} {elapsed} {msg:19} {prefix}")?;
+    // `Content-Length` is missing
+    let page_bytes_style = ProgressStyle::with_template(
+        "{spinner} {elapsed} {msg:19} {prefix} {bytes} {binary_bytes_per_sec}",
+    )?;
+    let page_bar_style = ProgressStyle::with_template(
+        "{spinner} {elapsed} {prefix} {wide_bar} {bytes}/{total_bytes} {binary_bytes_per_sec}",
+    )?;
+
+    let mut galleries = HashMap::<GalleryId, Gallery>::new();
+    let mut bars = HashMap::<(GalleryId, usize), ProgressBar>::new();
+
+    let mut tick = tokio::time::interval(config::PROGRESS_BAR_TICK_TIME);
+
+    loop {
+        let event = tokio::select! {
+            _ = &mut quit => break,
+            _ = tick.tick() => {
+                let now = Instant::now();
+                if limited {
+                    total_progress.set_message(total_message(&client));
+                }
+                total_bytes.sample(now);
+                total_progress.set_prefix(total_bytes.summary());
+                for gallery in galleries.values_mut() {
+                    gallery.bytes.sample(now);
+                    gallery.bar.set_prefix(gallery.stats());
+                }
+                for bar in bars.values() {
+                    bar.tick();
+                }
+                continue;
+            }
+            event = events.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+        };
+
+        let id = event.gallery;
+        match event.kind {
+            EventKind::GalleryDiscovered { site, .. } => {
+                total_progress.inc_length(1);
+                // a single gallery without a rate limit has nothing to add to its own bar
+                if !total_shown && (total_progress.length() > Some(1) || limited) {
+                    total_shown = true;
+                    progress.insert(0, total_progress.clone());
+                }
+
+                let bar = progress.add(
+                    ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr())
+                        .with_style(main_style.clone())
+                        .with_message(site),
+                );
+                galleries.insert(
+                    id,
+                    Gallery {
+                        bar,
+                        counted: false,
+                        bytes: Throughput::default(),
+                        failed: 0,
+                        retried: 0,
+                    },
+                );
+            }
+            EventKind::Metadata(metadata) => {
+                if let Some(gallery) = galleries.get(&id) {
+                    gallery
+                        .bar
+                        .set_message(format!("{} {:?}", metadata.site, metadata.title));
+                }
+            }
+            EventKind::PageCount { pages } => {
+                if let Some(gallery) = galleries.get_mut(&id) {
+                    gallery.counted = true;
+                    gallery.bar.set_length(pages as u64);
+                }
+            }
+            EventKind::PageQueued { .. } => {
+                if let Some(gallery) = galleries.get(&id) {
+                    if gallery.bar.length().is_none() {
+                        gallery.bar.set_length(0);
+                    }
+                    if !gallery.counted {
+                        gallery.bar.inc_length(1);
+                    }
+                }
+            }
+            EventKind::PageResolving { page } => {
+                let bar = ProgressBar::new_spinner()
+                    .with_style(page_style.clone())
+                    .with_message("Resolving Image Url");
+
+                // a retry restarts the page with a fresh spinner
+                if let Some(old) = bars.insert((id, page), progress.add(bar)) {
+                    progress.remove(&old);
+                }
+            }
+            EventKind::PageStarted { page, file_name } => {
+                if let Some(bar) = bars.get(&(id, page)) {
+                    bar.set_prefix(format!("{file_name:?}"));
+                    bar.set_message("Starting");
+                }
+            }
+            EventKind::PageSize { page, bytes } => {
+                if let Some(bar) = bars.get(&(id, page)) {
+                    bar.set_style(page_bar_style.clone());
+                    bar.set_length(bytes);
+                }
+            }
+            EventKind::BytesReceived { page, len } => {
+                if let Some(bar) = bars.get(&(id, page)) {
+                    if bar.length().is_none() && bar.position() == 0 {
+                        bar.set_style(page_bytes_style.clone());
+                        bar.set_message("Downloading");
+                    }
+                    bar.inc(len as u64);
+                }
+                if let Some(gallery) = galleries.get_mut(&id) {
+                    gallery.bytes.add(len);
+                }
+                total_bytes.add(len);
+            }
+            EventKind::PageDone { page } => {
+                if let Some(bar) = bars.remove(&(id, page)) {
+                    bar.finish_with_message("Done");
+                    progress.remove(&bar);
+                }
+                if let Some(gallery) = galleries.get(&id) {
+                    gallery.bar.inc(1);
+                }
+            }
+            EventKind::PageRetry {
+                page,
+                attempt,
+                error,
+                ..
+            } => {
+                if let Some(gallery) = galleries.get_mut(&id) {
+                    gallery.retried += 1;
+                }
+                progress.println(format!("page {page}: attempt {attempt} failed: {error}"))?;
+            }
+            EventKind::PageFailed { page, error, .. } => {
+                if let Some(bar) = bars.remove(&(id, page)) {
+                    progress.remove(&bar);
+                }
+                if let Some(gallery) = galleries.get_mut(&id) {
+                    gallery.failed += 1;
+                    gallery.bar.set_prefix(gallery.stats());
+                }
+                progress.println(format!("page {page} failed: {error}"))?;
+            }
+            EventKind::GalleryFinished { .. } => {
+                if let Some(gallery) = galleries.remove(&id) {
+                    gallery.bar.finish();
+                    progress.remove(&gallery.bar);
+                }
+                bars.retain(|&(gallery, _), bar| {
+                    if gallery == id {
+                        progress.remove(bar);
+                    }
+                    gallery != id
+                });
+                total_progress.inc(1);
+            }
+            _ => {}
+        }
+    }
+
+    progress.clear()?;
+
+    Ok(())
+}
@@ -0,0 +1,460 @@
+//! the config file: `[global]` settings and `[site.<name>]` sections on top of them;
+//! the command line and the environment win over both
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use hdl::{Client, ClientBuilder, SiteDefinition, SiteOptions};
+use hyper::Uri;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::args::{Args, ByteSize};
+use crate::cli::config;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Section {
+    out_dir: Option<PathBuf>,
+    dir_template: Option<String>,
+    retries: Option<usize>,
+    /// seconds
+    timeout: Option<u64>,
+    proxy: Option<String>,
+    cookies: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    /// total in `[global]`, of the site in `[site.<name>]`
+    limit_rate: Option<ByteSize>,
+    // global only
+    jobs: Option<usize>,
+    galleries: Option<usize>,
+}
+
+impl Section {
+    /// settings of `top` win, headers are replaced by name
+    fn overlay(&self, top: &Section) -> Section {
+        let mut headers = self.headers.clone();
+        for (name, value) in &top.headers {
+            headers.retain(|old, _| !old.eq_ignore_ascii_case(name));
+            headers.insert(name.clone(), value.clone());
+        }
+
+        Section {
+            out_dir: top.out_dir.clone().or_else(|| self.out_dir.clone()),
+            dir_template: top
+                .dir_template
+                .clone()
+                .or_else(|| self.dir_template.clone()),
+            retries: top.retries.or(self.retries),
+            timeout: top.timeout.or(self.timeout),
+            proxy: top.proxy.clone().or_else(|| self.proxy.clone()),
+            cookies: top.cookies.clone().or_else(|| self.cookies.clone()),
+            headers,
+            limit_rate: top.limit_rate.or(self.limit_rate),
+            jobs: top.jobs.or(self.jobs),
+            galleries: top.galleries.or(self.galleries),
+        }
+    }
+
+    fn site_options(&self) -> Result<SiteOptions> {
+        let mut options = SiteOptions::new();
+
+        if let Some(template) = &self.dir_template {
+            options = options.dir_template(template);
+        }
+        if let Some(retries) = self.retries {
+            options = options.retries(retries);
+        }
+        if let Some(timeout) = self.timeout {
+            options = options.timeout(Duration::from_secs(timeout));
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy =
+                Uri::try_from(proxy).with_context(|| format!("invalid proxy url: {proxy:?}"))?;
+            options = options.proxy(proxy);
+        }
+        if let Some(cookies) = &self.cookies {
+            options = options.cookies(cookies);
+        }
+        for (name, value) in &self.headers {
+            options = options.header(name, value);
+        }
+
+        Ok(options)
+    }
+}
+
+impl From<&Args> for Section {
+    fn from(args: &Args) -> Self {
+        Section {
+            out_dir: args.out_dir.clone(),
+            dir_template: args.dir_template.clone(),
+            retries: args.retries,
+            timeout: args.timeout,
+            proxy: args.proxy.clone(),
+            cookies: args.cookies.clone(),
+            headers: args
+                .headers
+                .iter()
+                .map(|header| (header.0.clone(), header.1.clone()))
+                .collect(),
+            limit_rate: args.limit_rate,
+            jobs: args.jobs,
+            galleries: args.galleries,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Settings {
+    global: Section,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    site: BTreeMap<String, Section>,
+    /// `sites/` next to the config file
+    #[serde(skip)]
+    sites_dir: Option<PathBuf>,
+}
+
+/// `$XDG_CONFIG_HOME/hdl/config.toml`
+pub(crate) fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    Some(config_home.join("hdl").join("config.toml"))
+}
+
+/// `~/` of paths from the config file, the shell expands the rest
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => path.to_owned(),
+    }
+}
+
+impl Settings {
+    /// the file at `path`, or at [`default_path`] if it exists;
+    /// then the command line and the environment from `args`
+    pub(crate) fn load(path: Option<&Path>, args: &Args) -> Result<Self> {
+        let file = path.map(Path::to_owned).or_else(default_path);
+        let settings = match &file {
+            Some(file) => match std::fs::read_to_string(file) {
+                Ok(content) => toml::from_str::<Settings>(&content)
+                    .with_context(|| format!("invalid config file: {file:?}"))?,
+                Err(e) if path.is_none() && e.kind() == std::io::ErrorKind::NotFound => {
+                    Settings::default()
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to read config file: {file:?}"))
+                }
+            },
+            None => Settings::default(),
+        };
+
+        // every request would time out, like `--timeout 0` is rejected
+        if settings.global.timeout == Some(0) {
+            bail!("[global]: timeout must be at least 1 second");
+        }
+        for (name, site) in &settings.site {
+            if site.jobs.is_some() || site.galleries.is_some() {
+                bail!("[site.{name}]: jobs and galleries can only be set in [global]");
+            }
+            if site.timeout == Some(0) {
+                bail!("[site.{name}]: timeout must be at least 1 second");
+            }
+        }
+
+        let cli = Section::from(args);
+        // `--limit-rate` is the total, sites keep their own
+        let cli_site = Section {
+            limit_rate: None,
+            jobs: None,
+            galleries: None,
+            ..cli.clone()
+        };
+
+        Ok(Settings {
+            global: settings.global.overlay(&cli),
+            site: settings
+                .site
+                .into_iter()
+                .map(|(name, site)| (name, site.overlay(&cli_site)))
+                .collect(),
+            sites_dir: file.and_then(|file| Some(file.parent()?.join("sites"))),
+        })
+    }
+
+    pub(crate) fn galleries(&self) -> usize {
+        self.global.galleries.unwrap_or(config::GALLERIES)
+    }
+
+    /// output directory of a site from [`Client::sites`]
+    pub(crate) fn out_dir(&self, site: &str) -> PathBuf {
+        let out_dir = self
+            .site
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(site))
+            .and_then(|(_, section)| section.out_dir.as_deref())
+            .or(self.global.out_dir.as_deref())
+            .unwrap_or(Path::new(config::OUT_DIR));
+
+        expand_home(out_dir)
+    }
+
+    /// the settings applied to `builder`;
+    /// fails on unknown site sections and invalid proxy urls
+    pub(crate) fn client(&self, builder: ClientBuilder) -> Result<Client> {
+        let defaults = self
+            .global
+            .site_options()
+            .context("invalid [global] config")?;
+        let mut builder = builder
+            .jobs(self.global.jobs.unwrap_or(config::JOBS))
+            .rate_limit(self.global.limit_rate.map(|rate| rate.0))
+            .defaults(defaults);
+        for definition in self.site_definitions()? {
+            builder = builder.site_definition(definition);
+        }
+        for (name, site) in &self.site {
+            let mut options = site
+                .site_options()
+                .with_context(|| format!("invalid [site.{name}] config"))?;
+            if let Some(rate) = site.limit_rate {
+                options = options.rate_limit(rate.0);
+            }
+            builder = builder.site(name, options);
+        }
+        let client = builder.build();
+
+        let sites = client.sites();
+        if let Some(name) = self
+            .site
+            .keys()
+            .find(|name| !sites.iter().any(|site| site.eq_ignore_ascii_case(name)))
+        {
+            let sites = sites
+                .iter()
+                .map(|site| site.to_lowercase())
+                .collect::<Vec<_>>();
+            bail!(
+                "unknown site in config: [site.{name}], expected one of: {}",
+                sites.join(", ")
+            );
+        }
+
+        Ok(client)
+    }
+
+    /// `*.toml` files of the sites directory, in name order
+    fn site_definitions(&self) -> Result<Vec<SiteDefinition>> {
+        let Some(dir) = &self.sites_dir else {
+            return Ok(Vec::new());
+        };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {dir:?}")),
+        };
+
+        let mut paths = entries
+            .map(|entry| Ok(entry?.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .with_context(|| format!("failed to read {dir:?}"))?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "toml"));
+        paths.sort();
+
+        paths
+            .iter()
+            .map(|path| {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read site definition: {path:?}"))?;
+                SiteDefinition::from_toml(&content)
+                    .with_context(|| format!("invalid site definition: {path:?}"))
+            })
+            .collect()
+    }
+
+    /// the settings in effect as toml, defaults filled in
+    pub(crate) fn show(&self) -> Result<String> {
+        let defaults = SiteOptions::new();
+        let mut global = self.global.clone();
+
+        global.out_dir = Some(expand_home(
+            global
+                .out_dir
+                .as_deref()
+                .unwrap_or(Path::new(config::OUT_DIR)),
+        ));
+        global
+            .dir_template
+            .get_or_insert_with(|| defaults.template().to_owned());
+        global.retries.get_or_insert(defaults.attempts());
+        global
+            .timeout
+            .get_or_insert(defaults.read_timeout().as_secs());
+        global.jobs.get_or_insert(config::JOBS);
+        global.galleries.get_or_insert(config::GALLERIES);
+
+        let settings = Settings {
+            global,
+            site: self.site.clone(),
+            sites_dir: None,
+        };
+        toml::to_string(&settings).context("failed to serialize the config")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    const URL: &str = "https://nhentai.net/g/123456/";
+
+    /// settings of the config file `content`, named `name` in the temp directory
+    fn load(name: &str, content: &str) -> Result<Settings> {
+        load_with(name, content, &[URL])
+    }
+
+    /// like [`load`], with the command line `args`
+    fn load_with(name: &str, content: &str, args: &[&str]) -> Result<Settings> {
+        let path = std::env::temp_dir().join(format!("hdl-{name}-{}.toml", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let args =
+            Args::try_parse_from(std::iter::once("hdl").chain(args.iter().copied())).unwrap();
+        let settings = Settings::load(Some(&path), &args);
+        std::fs::remove_file(&path).unwrap();
+        settings
+    }
+
+    fn section(content: &str) -> Section {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn overlays_sections() {
+        let file = section(
+            "retries = 5\ntimeout = 30\ncookies = \"a=1\"\n\
+             headers = { user-agent = \"file\", Referer = \"https://nhentai.net/\" }\n",
+        );
+        let cli = section("retries = 9\nheaders = { User-Agent = \"cli\" }\n");
+
+        let merged = file.overlay(&cli);
+        assert_eq!(merged.retries, Some(9));
+        assert_eq!(merged.timeout, Some(30));
+        assert_eq!(merged.cookies.as_deref(), Some("a=1"));
+        assert_eq!(merged.jobs, None);
+        // replaced whatever the case of the name
+        let headers = merged.headers.into_iter().collect::<Vec<_>>();
+        assert_eq!(
+            headers,
+            [
+                ("Referer".to_owned(), "https://nhentai.net/".to_owned()),
+                ("User-Agent".to_owned(), "cli".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefers_the_command_line_then_the_environment_then_the_file() {
+        let file = "[global]\nretries = 5\ntimeout = 30\ndir-template = \"{site}/{title}\"\n\
+                    [site.nhentai]\nretries = 2\ncookies = \"a=1\"\n";
+        // only read by this test
+        std::env::set_var("HDL_PROXY", "http://127.0.0.1:8080");
+        let settings = load_with(
+            "precedence",
+            file,
+            &["--retries", "9", "--proxy", "http://127.0.0.1:3128", URL],
+        );
+        let env = load_with("precedence-env", file, &[URL]);
+        std::env::remove_var("HDL_PROXY");
+        let (settings, env) = (settings.unwrap(), env.unwrap());
+
+        assert_eq!(settings.global.retries, Some(9));
+        assert_eq!(
+            settings.global.proxy.as_deref(),
+            Some("http://127.0.0.1:3128")
+        );
+        assert_eq!(env.global.proxy.as_deref(), Some("http://127.0.0.1:8080"));
+        assert_eq!(env.global.retries, Some(5));
+        assert_eq!(env.global.timeout, Some(30));
+        assert_eq!(env.global.jobs, None);
+        assert_eq!(env.galleries(), config::GALLERIES);
+
+        // the command line and the environment win over site sections too
+        let site = &settings.site["nhentai"];
+        assert_eq!(site.retries, Some(9));
+        assert_eq!(site.cookies.as_deref(), Some("a=1"));
+        assert_eq!(env.site["nhentai"].retries, Some(2));
+        assert_eq!(
+            env.site["nhentai"].proxy.as_deref(),
+            Some("http://127.0.0.1:8080")
+        );
+    }
+
+    #[test]
+    fn keeps_site_rate_limits_apart_from_the_total() {
+        let file = "[global]\nlimit-rate = \"2M\"\n[site.ehentai]\nlimit-rate = \"512K\"\n";
+        let settings = load_with("rates", file, &["--limit-rate", "1M", URL]).unwrap();
+        assert_eq!(settings.global.limit_rate.map(|rate| rate.0), Some(1 << 20));
+        assert_eq!(
+            settings.site["ehentai"].limit_rate.map(|rate| rate.0),
+            Some(512 << 10)
+        );
+
+        let error = load("site-jobs", "[site.ehentai]\njobs = 2\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "[site.ehentai]: jobs and galleries can only be set in [global]"
+        );
+    }
+
+    #[test]
+    fn shows_the_settings_with_defaults() {
+        let file = "[global]\nout-dir = \"/tmp/manga\"\nretries = 5\n\
+                    [site.ehentai]\ntimeout = 30\n";
+        let settings = load_with("show", file, &["--jobs", "4", URL]).unwrap();
+
+        assert_eq!(
+            settings.show().unwrap(),
+            "[global]\n\
+             out-dir = \"/tmp/manga\"\n\
+             dir-template = \"{title}\"\n\
+             retries = 5\n\
+             timeout = 10\n\
+             jobs = 4\n\
+             galleries = 1\n\
+             \n\
+             [site.ehentai]\n\
+             timeout = 30\n"
+        );
+    }
+
+    #[test]
+    fn rejects_a_zero_timeout() {
+        assert!(load("timeout", "[global]\ntimeout = 30\n").is_ok());
+
+        let error = load("global-timeout", "[global]\ntimeout = 0\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "[global]: timeout must be at least 1 second"
+        );
+
+        let error = load("site-timeout", "[site.nhentai]\ntimeout = 0\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "[site.nhentai]: timeout must be at least 1 second"
+        );
+    }
+}
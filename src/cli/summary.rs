@@ -0,0 +1,312 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use hdl::{ErrorKind, FailedPage};
+
+pub(crate) enum Outcome {
+    /// already downloaded according to the history
+    Skipped,
+    Succeeded,
+    PartiallySucceeded,
+    Failed,
+}
+
+/// exit status of the whole run, the codes are listed in the readme
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExitStatus {
+    Success = 0,
+    /// some pages or galleries failed, the rest was downloaded
+    Partial = 3,
+    /// nothing was downloaded
+    Failed = 4,
+    /// every failed gallery had an unsupported url
+    Unsupported = 5,
+    /// every failed gallery had a page the downloader couldn't parse
+    Parse = 6,
+    /// every failed gallery needed a login or was rate limited
+    Refused = 7,
+    /// every failed gallery doesn't exist or was removed
+    NotFound = 8,
+    /// every failed gallery couldn't reach its site
+    Network = 9,
+    /// stopped by SIGINT or SIGTERM, like shells report it
+    Interrupted = 130,
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status as u8)
+    }
+}
+
+pub(crate) struct GalleryReport {
+    pub url: String,
+    pub site: Option<&'static str>,
+    pub title: Option<String>,
+    pub out_dir: Option<PathBuf>,
+    pub skipped: bool,
+    pub pages: usize,
+    pub failed: Vec<FailedPage>,
+    /// error that stopped the gallery before all pages were scheduled
+    pub error: Option<anyhow::Error>,
+    /// cancelled before all pages were scheduled, or never started
+    pub interrupted: bool,
+}
+
+impl GalleryReport {
+    pub(crate) fn new(url: String) -> Self {
+        Self {
+            url,
+            site: None,
+            title: None,
+            out_dir: None,
+            skipped: false,
+            pages: 0,
+            failed: Vec::new(),
+            error: None,
+            interrupted: false,
+        }
+    }
+
+    pub(crate) fn finish(&mut self, summary: hdl::Summary) {
+        if let Some(metadata) = summary.metadata {
+            self.title = Some(metadata.title);
+            self.out_dir = Some(metadata.out_dir);
+        }
+        self.pages = summary.pages;
+        self.failed = summary.failed;
+        self.error = summary.error.map(anyhow::Error::from);
+        self.interrupted = summary.cancelled;
+    }
+
+    pub(crate) fn outcome(&self) -> Outcome {
+        if self.skipped {
+            return Outcome::Skipped;
+        }
+
+        let downloaded = self.pages - self.failed.len();
+        match (&self.error, self.failed.len()) {
+            (None, 0) if !self.interrupted => Outcome::Succeeded,
+            _ if downloaded > 0 => Outcome::PartiallySucceeded,
+            _ => Outcome::Failed,
+        }
+    }
+
+    /// kinds of the gallery error and of the failed pages
+    fn kinds(&self) -> impl Iterator<Item = ErrorKind> + '_ {
+        let pages = self.failed.iter().map(|page| page.error.kind().clone());
+        self.error.iter().map(ErrorKind::of).chain(pages)
+    }
+}
+
+impl ExitStatus {
+    /// the status of a run where every failed gallery failed with `kind`
+    fn of(kind: &ErrorKind) -> Option<ExitStatus> {
+        match kind {
+            ErrorKind::Unsupported => Some(ExitStatus::Unsupported),
+            ErrorKind::Parse { .. } => Some(ExitStatus::Parse),
+            ErrorKind::AuthRequired | ErrorKind::RateLimited => Some(ExitStatus::Refused),
+            ErrorKind::NotFound => Some(ExitStatus::NotFound),
+            ErrorKind::Network => Some(ExitStatus::Network),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn exit_status(reports: &[GalleryReport]) -> ExitStatus {
+    let failed = reports
+        .iter()
+        .filter(|r| matches!(r.outcome(), Outcome::Failed))
+        .collect::<Vec<_>>();
+    let partial = reports
+        .iter()
+        .any(|r| matches!(r.outcome(), Outcome::PartiallySucceeded));
+
+    // a gallery without an error failed on every page, the first one stands for all
+    let status = |report: &&GalleryReport| report.kinds().next().as_ref().and_then(ExitStatus::of);
+
+    if failed.is_empty() && !partial {
+        ExitStatus::Success
+    } else if failed.len() < reports.len() {
+        ExitStatus::Partial
+    } else {
+        let first = failed.first().and_then(status);
+        match first {
+            Some(first) if failed.iter().all(|r| status(r) == Some(first)) => first,
+            _ => ExitStatus::Failed,
+        }
+    }
+}
+
+/// what can be done about an error of this kind
+fn hint(kind: &ErrorKind) -> Option<&'static str> {
+    match kind {
+        ErrorKind::Parse { .. } => Some(
+            "the site layout may have changed, `--debug-dump <DIR>` saves the pages for a bug report",
+        ),
+        ErrorKind::AuthRequired => {
+            Some("pass the cookies of a logged in browser session with `--cookies`")
+        }
+        ErrorKind::RateLimited => Some("the site is throttling, lower `--jobs` or use `--limit-rate`"),
+        ErrorKind::Network => Some("check the connection or `--proxy`, or raise `--timeout`"),
+        _ => None,
+    }
+}
+
+/// one hint per kind of error in `reports`
+pub(crate) fn print_hints(reports: &[GalleryReport]) {
+    let mut hints = reports
+        .iter()
+        .flat_map(GalleryReport::kinds)
+        .filter_map(|kind| hint(&kind))
+        .collect::<Vec<_>>();
+    hints.sort();
+    hints.dedup();
+    for hint in hints {
+        eprintln!("hint: {hint}");
+    }
+}
+
+/// every failed page with its url and last error
+pub(crate) fn print_failed_pages(report: &GalleryReport, indent: &str) {
+    for page in &report.failed {
+        eprintln!("{indent}page {} {}: {:#}", page.page, page.url, page.error);
+    }
+}
+
+pub(crate) fn print_summary(reports: &[GalleryReport]) {
+    let count = |f: fn(&Outcome) -> bool| reports.iter().filter(|r| f(&r.outcome())).count();
+
+    eprintln!(
+        "{} succeeded, {} partially succeeded, {} failed, {} skipped",
+        count(|o| matches!(o, Outcome::Succeeded)),
+        count(|o| matches!(o, Outcome::PartiallySucceeded)),
+        count(|o| matches!(o, Outcome::Failed)),
+        count(|o| matches!(o, Outcome::Skipped)),
+    );
+
+    for report in reports {
+        let status = match report.outcome() {
+            _ if report.interrupted => "interrupted",
+            Outcome::Skipped => "skipped",
+            Outcome::Succeeded => "ok",
+            Outcome::PartiallySucceeded => "partial",
+            Outcome::Failed => "failed",
+        };
+        let downloaded = report.pages - report.failed.len();
+
+        eprint!("  [{status}] {}", report.url);
+        if let Some(title) = &report.title {
+            eprint!(" {title:?}");
+        }
+        eprintln!(" ({downloaded}/{} pages)", report.pages);
+
+        if let Some(e) = &report.error {
+            eprintln!("    {e:#}");
+        }
+        print_failed_pages(report, "    ");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use hyper::StatusCode;
+
+    use super::*;
+
+    /// a gallery of `pages` pages, the `failed` ones failing with `kind`
+    fn report(pages: usize, failed: &[usize], kind: ErrorKind) -> GalleryReport {
+        let mut report = GalleryReport::new("https://nhentai.net/g/123456/".to_owned());
+        report.pages = pages;
+        report.failed = failed
+            .iter()
+            .map(|&page| {
+                let url = format!("https://nhentai.net/g/123456/{page}/");
+                FailedPage::new(page, url, hdl::Error::msg(kind.clone(), "page failed"))
+            })
+            .collect();
+        report
+    }
+
+    fn stopped(kind: ErrorKind) -> GalleryReport {
+        let mut report = GalleryReport::new("https://nhentai.net/g/123456/".to_owned());
+        report.error = Some(anyhow!("gallery failed").context(kind));
+        report
+    }
+
+    #[test]
+    fn tells_the_outcome() {
+        assert!(matches!(
+            report(5, &[], ErrorKind::Other).outcome(),
+            Outcome::Succeeded
+        ));
+        assert!(matches!(
+            report(5, &[2], ErrorKind::Network).outcome(),
+            Outcome::PartiallySucceeded
+        ));
+        assert!(matches!(
+            report(2, &[1, 2], ErrorKind::Network).outcome(),
+            Outcome::Failed
+        ));
+        assert!(matches!(
+            stopped(ErrorKind::NotFound).outcome(),
+            Outcome::Failed
+        ));
+
+        let mut interrupted = report(0, &[], ErrorKind::Other);
+        interrupted.interrupted = true;
+        assert!(matches!(interrupted.outcome(), Outcome::Failed));
+
+        let mut skipped = stopped(ErrorKind::Network);
+        skipped.skipped = true;
+        assert!(matches!(skipped.outcome(), Outcome::Skipped));
+    }
+
+    #[test]
+    fn exits_with_the_kind_of_every_failure() {
+        let kinds = [
+            (ErrorKind::Unsupported, ExitStatus::Unsupported),
+            (ErrorKind::Parse { selector: None }, ExitStatus::Parse),
+            (ErrorKind::AuthRequired, ExitStatus::Refused),
+            (ErrorKind::RateLimited, ExitStatus::Refused),
+            (ErrorKind::NotFound, ExitStatus::NotFound),
+            (ErrorKind::Network, ExitStatus::Network),
+            (ErrorKind::Http(StatusCode::BAD_GATEWAY), ExitStatus::Failed),
+        ];
+        for (kind, status) in kinds {
+            assert_eq!(exit_status(&[stopped(kind.clone())]), status, "{kind:?}");
+            // every page failed the same way
+            assert_eq!(
+                exit_status(&[report(2, &[1, 2], kind.clone())]),
+                status,
+                "{kind:?}"
+            );
+        }
+        assert_eq!(ExitStatus::Parse as u8, 6);
+        assert_eq!(ExitStatus::Network as u8, 9);
+    }
+
+    #[test]
+    fn exits_partial_or_failed_on_mixed_results() {
+        assert_eq!(exit_status(&[]), ExitStatus::Success);
+        assert_eq!(
+            exit_status(&[report(3, &[], ErrorKind::Other)]),
+            ExitStatus::Success
+        );
+
+        let partial = [report(3, &[2], ErrorKind::Network)];
+        assert_eq!(exit_status(&partial), ExitStatus::Partial);
+        assert_eq!(ExitStatus::Partial as u8, 3);
+
+        let one_failed = [
+            report(3, &[], ErrorKind::Other),
+            stopped(ErrorKind::NotFound),
+        ];
+        assert_eq!(exit_status(&one_failed), ExitStatus::Partial);
+
+        let different = [stopped(ErrorKind::NotFound), stopped(ErrorKind::Network)];
+        assert_eq!(exit_status(&different), ExitStatus::Failed);
+        assert_eq!(ExitStatus::Failed as u8, 4);
+    }
+}
@@ -0,0 +1,98 @@
+//! a run of the client against canned responses, for the tests of the progress printers
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use hdl::{Client, DownloadOptions, Event, MockTransport, PageRange};
+
+/// the pages of the first gallery: 2 is retried and 3 fails
+pub(crate) const GALLERY: &str = "https://nhentai.net/g/123456/";
+/// doesn't exist
+pub(crate) const REMOVED: &str = "https://nhentai.net/g/654321/";
+/// superseded by [`EHENTAI`]
+pub(crate) const OLD: &str = "https://e-hentai.org/g/1111111/aaaaaaaaaa/";
+pub(crate) const EHENTAI: &str = "https://e-hentai.org/g/1234567/0123456789/";
+const EHENTAI_IMAGE: &str = "https://abcdefg.hijklmnop.hath.network/h/0123456789abcdef/keystamp=1700000000-abcdef;fileindex=123456;xres=1280/001.jpg";
+
+/// page saved in `tests/fixtures/`
+fn fixture(path: &str) -> Vec<u8> {
+    std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path),
+    )
+    .unwrap()
+}
+
+fn image(page: usize) -> String {
+    format!("https://i3.nhentai.net/galleries/987654/{page}.jpg")
+}
+
+/// one page at a time, so that the events come in a fixed order
+pub(crate) fn client() -> Client {
+    let mock = MockTransport::new()
+        .page(GALLERY, fixture("nhentai/gallery.html"))
+        .page(
+            "https://nhentai.net/g/123456/1/",
+            fixture("nhentai/image.html"),
+        )
+        .page(&image(1), "image 1")
+        .error(&image(2), "connection reset")
+        .page(&image(2), "image 2")
+        .page(OLD, fixture("ehentai/gallery_updated.html"))
+        .page(EHENTAI, fixture("ehentai/gallery.html"))
+        .page(
+            "https://e-hentai.org/s/0000003dde/1234567-2",
+            fixture("ehentai/image.html"),
+        )
+        .page(EHENTAI_IMAGE, "image")
+        .page(
+            "https://api.e-hentai.org/api.php",
+            fixture("ehentai/gdata.json"),
+        );
+
+    Client::builder().transport(Arc::new(mock)).jobs(1).build()
+}
+
+/// downloads the galleries one after another into `out`, the e-hentai one
+/// into `out/ehentai`
+pub(crate) async fn run(client: &Client, out: &Path) {
+    let pages = |pages: &str| pages.parse::<PageRange>().unwrap();
+
+    let gallery = client.gallery(GALLERY).unwrap();
+    let options = DownloadOptions::new(out).pages(pages("1-3"));
+    gallery.download(options).finish().await;
+
+    let removed = client.gallery(REMOVED).unwrap();
+    removed.download(DownloadOptions::new(out)).finish().await;
+
+    let newest = client.gallery(OLD).unwrap().latest().await.unwrap();
+    let options = DownloadOptions::new(out.join("ehentai")).pages(pages("2"));
+    newest.download(options).finish().await;
+}
+
+/// removed before and after the test
+pub(crate) struct OutDir(pub PathBuf);
+
+impl OutDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("hdl-run-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Self(dir)
+    }
+}
+
+impl Drop for OutDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// the events of [`run`], downloading into `out`
+pub(crate) async fn events(out: &OutDir) -> Vec<Event> {
+    let client = client();
+    let mut events = client.subscribe();
+    run(&client, &out.0).await;
+
+    std::iter::from_fn(|| events.try_recv()).collect()
+}
@@ -0,0 +1,810 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use hyper::Uri;
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tracing::{debug, info, warn, Instrument};
+
+use crate::downloaders::{self, Downloader, SiteDefinition};
+use crate::engine::{self, Mode};
+use crate::error::{Error, ErrorKind};
+use crate::event::{Event, EventBus, GalleryId, Subscription};
+use crate::page_range::PageRange;
+use crate::rate_limit::RateLimiter;
+use crate::request::HttpTransport;
+use crate::site_options::SiteOptions;
+use crate::transport::{Ctx, Transport};
+
+/// entry point of the library, cheap to clone
+///
+/// ```no_run
+/// # async fn run() -> hdl::Result<()> {
+/// let client = hdl::Client::builder().jobs(4).build();
+/// let gallery = client.gallery("https://nhentai.net/g/123/")?;
+///
+/// let mut download = gallery.download(hdl::DownloadOptions::new("./out"));
+/// while let Some(event) = download.next_event().await {
+///     println!("{:?}", event.kind);
+/// }
+/// let summary = download.finish().await;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Client {
+    pub(crate) inner: Arc<Inner>,
+}
+
+pub(crate) struct Inner {
+    pub(crate) downloaders: Vec<Arc<dyn Downloader>>,
+    transport: Arc<dyn Transport>,
+    debug_dump: Option<Arc<Path>>,
+    /// image downloads in flight across all galleries
+    pub(crate) jobs: Arc<Semaphore>,
+    pub(crate) limiter: RateLimiter,
+    /// by site name, for sites with [`SiteOptions::rate_limit`]
+    site_limiters: Mutex<HashMap<&'static str, Arc<RateLimiter>>>,
+    pub(crate) events: EventBus,
+    pub(crate) cancel: watch::Sender<Cancel>,
+    defaults: SiteOptions,
+    /// by lowercase site name
+    sites: HashMap<String, SiteOptions>,
+    next_gallery: AtomicU64,
+    /// by gallery url, filled by [`Client::prefetch_details`]
+    details: Mutex<HashMap<String, watch::Receiver<Option<GalleryDetails>>>>,
+}
+
+impl Inner {
+    fn site_options(&self, site: &str) -> SiteOptions {
+        match self.sites.get(&site.to_lowercase()) {
+            Some(options) => self.defaults.merge(options),
+            None => self.defaults.clone(),
+        }
+    }
+
+    fn ctx(&self, site: &'static str) -> Ctx {
+        let options = self.site_options(site);
+        let limiter = options.rate_limit.map(|rate| self.site_limiter(site, rate));
+        Ctx::new(Arc::clone(&self.transport), site, options)
+            .debug_dump(self.debug_dump.clone())
+            .limiter(limiter)
+    }
+
+    /// one limiter per site, shared by all its galleries
+    fn site_limiter(&self, site: &'static str, rate: u64) -> Arc<RateLimiter> {
+        let mut limiters = self.site_limiters.lock().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(
+            limiters
+                .entry(site)
+                .or_insert_with(|| Arc::new(RateLimiter::new(rate))),
+        )
+    }
+
+    /// details of `url` from [`Client::prefetch_details`], or asked for alone;
+    /// empty if the site has no api or it failed
+    pub(crate) async fn details(
+        &self,
+        downloader: &Arc<dyn Downloader>,
+        ctx: &Ctx,
+        url: &Uri,
+    ) -> GalleryDetails {
+        if downloader.details_batch() == 0 {
+            return GalleryDetails::default();
+        }
+
+        let prefetched = self
+            .details
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&url.to_string());
+        if let Some(mut prefetched) = prefetched {
+            // the batch was dropped if this fails, ask alone then
+            if let Ok(details) = prefetched.wait_for(Option::is_some).await {
+                return details.clone().unwrap_or_default();
+            }
+        }
+
+        fetch_details(downloader, ctx, std::slice::from_ref(url))
+            .await
+            .pop()
+            .unwrap_or_default()
+    }
+}
+
+/// details of `galleries` in one call, empty ones if it fails
+async fn fetch_details(
+    downloader: &Arc<dyn Downloader>,
+    ctx: &Ctx,
+    galleries: &[Uri],
+) -> Vec<GalleryDetails> {
+    let details = timeout(
+        ctx.site().read_timeout(),
+        downloader.details(ctx, galleries),
+    )
+    .await
+    .context("timed out waiting for gallery details")
+    .context(ErrorKind::Network)
+    .and_then(|details| details);
+
+    match details {
+        Ok(mut details) => {
+            details.resize_with(galleries.len(), GalleryDetails::default);
+            details
+        }
+        Err(e) => {
+            warn!(
+                site = downloader.name(),
+                galleries = galleries.len(),
+                error = format!("{e:#}"),
+                "failed to get gallery details"
+            );
+            vec![GalleryDetails::default(); galleries.len()]
+        }
+    }
+}
+
+/// how far the client was cancelled, only ever goes up
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Cancel {
+    Running,
+    /// no new pages are scheduled
+    Stopping,
+    /// pages in flight are interrupted too
+    Aborting,
+}
+
+pub struct ClientBuilder {
+    jobs: usize,
+    rate_limit: Option<u64>,
+    defaults: SiteOptions,
+    sites: HashMap<String, SiteOptions>,
+    definitions: Vec<SiteDefinition>,
+    transport: Arc<dyn Transport>,
+    debug_dump: Option<PathBuf>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            jobs: 3,
+            rate_limit: None,
+            defaults: SiteOptions::default(),
+            sites: HashMap::new(),
+            definitions: Vec::new(),
+            transport: Arc::new(HttpTransport),
+            debug_dump: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// images downloaded at the same time, shared by every gallery of the client
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// total download rate in bytes per second
+    pub fn rate_limit(mut self, rate: Option<u64>) -> Self {
+        self.rate_limit = rate;
+        self
+    }
+
+    /// settings of every site
+    pub fn defaults(mut self, options: SiteOptions) -> Self {
+        self.defaults = options;
+        self
+    }
+
+    /// settings of one site, on top of [`ClientBuilder::defaults`];
+    /// `site` is a name from [`Client::sites`], in any case
+    pub fn site(mut self, site: &str, options: SiteOptions) -> Self {
+        self.sites.insert(site.to_lowercase(), options);
+        self
+    }
+
+    /// how requests are sent, [`HttpTransport`] by default
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// save pages that fail to parse to `dir`, with the url and the error
+    pub fn debug_dump(mut self, dir: Option<PathBuf>) -> Self {
+        self.debug_dump = dir;
+        self
+    }
+
+    /// site matched before the built-in ones, replaces the site of the same name
+    pub fn site_definition(mut self, definition: SiteDefinition) -> Self {
+        self.definitions
+            .retain(|old| !old.name().eq_ignore_ascii_case(definition.name()));
+        self.definitions.push(definition);
+        self
+    }
+
+    pub fn build(self) -> Client {
+        let is_overridden = |name: &str| {
+            self.definitions
+                .iter()
+                .any(|definition| definition.name().eq_ignore_ascii_case(name))
+        };
+        let builtin = downloaders::downloaders()
+            .iter()
+            .filter(|downloader| !is_overridden(downloader.name()))
+            .cloned()
+            .collect::<Vec<_>>();
+        let downloaders = self
+            .definitions
+            .iter()
+            .cloned()
+            .map(SiteDefinition::downloader)
+            .chain(builtin)
+            .collect();
+
+        Client {
+            inner: Arc::new(Inner {
+                downloaders,
+                transport: self.transport,
+                debug_dump: self.debug_dump.map(Arc::from),
+                jobs: Arc::new(Semaphore::new(self.jobs)),
+                limiter: RateLimiter::new(self.rate_limit.unwrap_or(0)),
+                site_limiters: Mutex::default(),
+                events: EventBus::default(),
+                cancel: watch::Sender::new(Cancel::Running),
+                defaults: self.defaults,
+                sites: self.sites,
+                next_gallery: AtomicU64::new(0),
+                details: Mutex::default(),
+            }),
+        }
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Client {
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// find the downloader for a gallery url, fails with [`ErrorKind::Unsupported`] if none matches
+    pub fn gallery(&self, url: &str) -> Result<GalleryHandle, Error> {
+        let uri = Uri::try_from(url)
+            .with_context(|| format!("invalid url: {url:?}"))
+            .map_err(Error::from_anyhow)?;
+
+        let downloader = self
+            .inner
+            .downloaders
+            .iter()
+            .find(|d| d.is_gallery_match(&uri))
+            .ok_or_else(|| {
+                Error::msg(ErrorKind::Unsupported, format!("no downloader for {url:?}"))
+            })?;
+
+        Ok(GalleryHandle {
+            client: self.clone(),
+            downloader: Arc::clone(downloader),
+            url: Arc::new(uri),
+            redirected_from: None,
+        })
+    }
+
+    /// whether `url` is a search, tag, artist or group listing of a supported site
+    pub fn is_listing(&self, url: &str) -> bool {
+        let Ok(uri) = Uri::try_from(url) else {
+            return false;
+        };
+        self.inner
+            .downloaders
+            .iter()
+            .any(|d| d.is_listing_match(&uri))
+    }
+
+    /// galleries of a listing url, following its pages in order
+    ///
+    /// Fails if the first page can't be read; a later page that fails ends the
+    /// listing with the galleries found so far. Stops early once the client is
+    /// cancelled.
+    pub async fn list(
+        &self,
+        url: &str,
+        options: ListingOptions,
+    ) -> Result<Vec<ListedGallery>, Error> {
+        self.list_galleries(url, options)
+            .await
+            .map_err(Error::from_anyhow)
+    }
+
+    async fn list_galleries(
+        &self,
+        url: &str,
+        options: ListingOptions,
+    ) -> Result<Vec<ListedGallery>> {
+        let uri = Uri::try_from(url).with_context(|| format!("invalid url: {url:?}"))?;
+        let downloader = self
+            .inner
+            .downloaders
+            .iter()
+            .find(|d| d.is_listing_match(&uri))
+            .ok_or_else(|| anyhow!("no listing for {url:?}").context(ErrorKind::Unsupported))?;
+        let ctx = self.inner.ctx(downloader.name());
+
+        let span = tracing::info_span!("listing", site = downloader.name(), url = %uri);
+        async {
+            let mut galleries = Vec::new();
+            let mut seen = HashSet::new();
+            let mut next = Some(uri);
+            let mut pages = 0;
+
+            while let Some(url) = next.take() {
+                if options.max_pages.is_some_and(|max| pages >= max)
+                    || options.is_full(galleries.len())
+                    || self.is_cancelled()
+                {
+                    break;
+                }
+                pages += 1;
+
+                let page = match downloader.listing_page(&ctx, &url).await {
+                    Ok(page) => page,
+                    Err(e) if pages == 1 => {
+                        return Err(e.context(format!("failed to list galleries of {url}")))
+                    }
+                    Err(e) => {
+                        warn!(
+                            page = pages,
+                            error = format!("{e:#}"),
+                            "listing stopped early"
+                        );
+                        break;
+                    }
+                };
+                debug!(
+                    page = pages,
+                    galleries = page.galleries.len(),
+                    "listing page"
+                );
+                // a page without galleries would loop forever
+                if page.galleries.is_empty() {
+                    break;
+                }
+
+                for gallery in page.galleries {
+                    if !seen.insert(gallery.url.clone()) || !options.keeps(&gallery) {
+                        continue;
+                    }
+                    galleries.push(gallery);
+                    if options.is_full(galleries.len()) {
+                        break;
+                    }
+                }
+                next = page.next;
+            }
+
+            info!(galleries = galleries.len(), pages, "listing finished");
+            Ok(galleries)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// ask the sites with an api for the details of `urls` in the background,
+    /// many galleries per request; their downloads then don't ask one by one
+    ///
+    /// Must be called within a tokio runtime, urls of other sites are ignored.
+    pub fn prefetch_details<I>(&self, urls: I)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        type Batch = (
+            Arc<dyn Downloader>,
+            Vec<(Uri, watch::Sender<Option<GalleryDetails>>)>,
+        );
+        let mut batches = HashMap::<&'static str, Batch>::new();
+
+        let mut cache = self.inner.details.lock().unwrap_or_else(|e| e.into_inner());
+        for url in urls {
+            let Ok(uri) = Uri::try_from(url.as_ref()) else {
+                continue;
+            };
+            let Some(downloader) = self
+                .inner
+                .downloaders
+                .iter()
+                .find(|d| d.is_gallery_match(&uri) && d.details_batch() > 0)
+            else {
+                continue;
+            };
+            if cache.contains_key(&uri.to_string()) {
+                continue;
+            }
+
+            let (tx, rx) = watch::channel(None);
+            cache.insert(uri.to_string(), rx);
+            batches
+                .entry(downloader.name())
+                .or_insert_with(|| (Arc::clone(downloader), Vec::new()))
+                .1
+                .push((uri, tx));
+        }
+        drop(cache);
+
+        for (site, (downloader, galleries)) in batches {
+            let ctx = self.inner.ctx(site);
+            let size = downloader.details_batch();
+            // one batch after another, the apis ask not to be flooded
+            tokio::spawn(
+                async move {
+                    for batch in galleries.chunks(size) {
+                        let urls = batch.iter().map(|(url, _)| url.clone()).collect::<Vec<_>>();
+                        let details = fetch_details(&downloader, &ctx, &urls).await;
+                        for ((_, tx), details) in batch.iter().zip(details) {
+                            tx.send_replace(Some(details));
+                        }
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+    }
+
+    /// names of the supported sites, as in [`GalleryHandle::site`]
+    pub fn sites(&self) -> Vec<&'static str> {
+        self.inner.downloaders.iter().map(|d| d.name()).collect()
+    }
+
+    /// events of every gallery downloaded by this client from now on;
+    /// no event is dropped, but while the subscriber lags behind the
+    /// [`EventKind::BytesReceived`] of a page are merged into one with the summed `len`
+    ///
+    /// [`EventKind::BytesReceived`]: crate::EventKind::BytesReceived
+    pub fn subscribe(&self) -> Subscription {
+        self.inner.events.subscribe(None)
+    }
+
+    /// current total download rate limit in bytes per second
+    pub fn rate_limit(&self) -> Option<u64> {
+        self.inner.limiter.rate()
+    }
+
+    /// change the rate limit of downloads that are already running
+    pub fn set_rate_limit(&self, rate: Option<u64>) {
+        self.inner.limiter.set_rate(rate.unwrap_or(0));
+    }
+
+    /// stop scheduling new pages, pages in flight are still finished;
+    /// see [`Summary::cancelled`]
+    pub fn cancel(&self) {
+        self.escalate(Cancel::Stopping);
+    }
+
+    /// also interrupt the pages in flight, they fail with [`ErrorKind::Cancelled`]
+    /// and leave no partial files behind
+    pub fn abort(&self) {
+        self.escalate(Cancel::Aborting);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.inner.cancel.borrow() >= Cancel::Stopping
+    }
+
+    /// resolves once [`Client::cancel`] or [`Client::abort`] is called
+    pub async fn cancelled(&self) {
+        engine::wait_cancel(&mut self.inner.cancel.subscribe(), Cancel::Stopping).await;
+    }
+
+    fn escalate(&self, cancel: Cancel) {
+        self.inner.cancel.send_if_modified(|current| {
+            let raised = *current < cancel;
+            *current = (*current).max(cancel);
+            raised
+        });
+    }
+}
+
+/// a gallery url matched to one of the supported sites
+#[derive(Clone)]
+pub struct GalleryHandle {
+    client: Client,
+    downloader: Arc<dyn Downloader>,
+    url: Arc<Uri>,
+    /// url asked for, when [`GalleryHandle::latest`] found a newer revision
+    redirected_from: Option<Arc<Uri>>,
+}
+
+impl GalleryHandle {
+    pub fn url(&self) -> String {
+        self.url.to_string()
+    }
+
+    /// the url this handle was asked for, if [`GalleryHandle::latest`] replaced it
+    pub fn redirected_from(&self) -> Option<String> {
+        self.redirected_from.as_ref().map(|url| url.to_string())
+    }
+
+    /// the newest revision of the gallery when the site lists newer ones
+    /// (e-hentai), otherwise the gallery itself; the download then records
+    /// the old url in [`Metadata::redirected_from`]
+    pub async fn latest(&self) -> Result<GalleryHandle, Error> {
+        let ctx = self.client.inner.ctx(self.downloader.name());
+        let newer = self
+            .downloader
+            .newer_version(&ctx, &self.url)
+            .await
+            .with_context(|| format!("failed to look for a newer version of {}", self.url))
+            .map_err(Error::from_anyhow)?;
+
+        Ok(match newer {
+            Some(url) if url != *self.url => {
+                tracing::info!(from = %self.url, to = %url, "newer version found");
+                GalleryHandle {
+                    url: Arc::new(url),
+                    redirected_from: Some(
+                        self.redirected_from
+                            .clone()
+                            .unwrap_or_else(|| Arc::clone(&self.url)),
+                    ),
+                    ..self.clone()
+                }
+            }
+            _ => self.clone(),
+        })
+    }
+
+    /// name of the site downloader, e.g. `"Nhentai"`
+    pub fn site(&self) -> &'static str {
+        self.downloader.name()
+    }
+
+    /// site specific gallery id parsed from the url
+    pub fn gallery_id(&self) -> Option<String> {
+        self.downloader.gallery_id(&self.url)
+    }
+
+    /// download the gallery in background
+    pub fn download(&self, options: DownloadOptions) -> Download {
+        self.start(options, Mode::Download)
+    }
+
+    /// find the image urls without downloading them, see [`Summary::resolved`]
+    pub fn resolve(&self, options: DownloadOptions) -> Download {
+        self.start(options, Mode::Resolve)
+    }
+
+    fn start(&self, options: DownloadOptions, mode: Mode) -> Download {
+        let inner = &self.client.inner;
+        let id = GalleryId(inner.next_gallery.fetch_add(1, Ordering::Relaxed));
+        // subscribe before starting so no event is missed
+        let events = inner.events.subscribe(Some(id));
+
+        let span = tracing::info_span!(
+            "gallery",
+            id = %id,
+            site = self.downloader.name(),
+            url = %self.url,
+        );
+        let task = tokio::spawn(
+            engine::run(
+                Arc::clone(inner),
+                id,
+                Arc::clone(&self.downloader),
+                Arc::clone(&self.url),
+                self.redirected_from.clone(),
+                Arc::new(options),
+                inner.ctx(self.downloader.name()),
+                mode,
+            )
+            .instrument(span),
+        );
+
+        Download { id, events, task }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DownloadOptions {
+    pub(crate) out_dir: PathBuf,
+    pub(crate) pages: PageRange,
+    pub(crate) overwrite: bool,
+}
+
+impl DownloadOptions {
+    /// the gallery gets its own directory named after the title inside `out_dir`
+    pub fn new<P: AsRef<Path>>(out_dir: P) -> Self {
+        Self {
+            out_dir: out_dir.as_ref().to_owned(),
+            pages: PageRange::default(),
+            overwrite: false,
+        }
+    }
+
+    /// download only the selected pages
+    pub fn pages(mut self, pages: PageRange) -> Self {
+        self.pages = pages;
+        self
+    }
+
+    /// download images again even if they are already on disk
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+}
+
+/// what [`Client::list`] keeps of a listing, everything by default
+#[derive(Clone, Debug, Default)]
+pub struct ListingOptions {
+    max_galleries: Option<usize>,
+    max_pages: Option<usize>,
+    language: Option<String>,
+}
+
+impl ListingOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// stop after this many galleries
+    pub fn max_galleries(mut self, max: Option<usize>) -> Self {
+        self.max_galleries = max;
+        self
+    }
+
+    /// read at most this many listing pages
+    pub fn max_pages(mut self, max: Option<usize>) -> Self {
+        self.max_pages = max;
+        self
+    }
+
+    /// keep only galleries the listing tells are in `language`, e.g. `english`
+    pub fn language(mut self, language: Option<&str>) -> Self {
+        self.language = language.map(str::to_lowercase);
+        self
+    }
+
+    fn is_full(&self, galleries: usize) -> bool {
+        self.max_galleries.is_some_and(|max| galleries >= max)
+    }
+
+    fn keeps(&self, gallery: &ListedGallery) -> bool {
+        match &self.language {
+            Some(language) if gallery.language.as_ref() != Some(language) => {
+                debug!(url = gallery.url, language = ?gallery.language, "filtered out");
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// running download (or resolution) of a gallery
+pub struct Download {
+    id: GalleryId,
+    events: Subscription,
+    task: JoinHandle<Summary>,
+}
+
+impl Download {
+    /// id used in the events of this download
+    pub fn id(&self) -> GalleryId {
+        self.id
+    }
+
+    /// events of this gallery only, `None` after [`EventKind::GalleryFinished`];
+    /// see [`Client::subscribe`] for events of every gallery
+    ///
+    /// [`EventKind::GalleryFinished`]: crate::EventKind::GalleryFinished
+    pub async fn next_event(&mut self) -> Option<Event> {
+        self.events.recv().await
+    }
+
+    /// wait for the gallery to finish, remaining events are dropped
+    pub async fn finish(self) -> Summary {
+        drop(self.events);
+        match self.task.await {
+            Ok(summary) => summary,
+            Err(e) => Summary {
+                error: Some(Error::from_anyhow(
+                    anyhow!(e).context("gallery task failed"),
+                )),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Metadata {
+    pub site: &'static str,
+    pub url: String,
+    pub title: String,
+    pub out_dir: PathBuf,
+    /// the older revision the gallery was asked for as, see [`GalleryHandle::latest`]
+    pub redirected_from: Option<String>,
+    /// empty unless the site has an api for them
+    pub details: GalleryDetails,
+}
+
+/// what a site api tells about a gallery, see [`Client::prefetch_details`]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct GalleryDetails {
+    /// title in the original language, usually japanese
+    pub title_original: Option<String>,
+    pub category: Option<String>,
+    pub uploader: Option<String>,
+    /// unix time in seconds
+    pub posted: Option<u64>,
+    pub pages: Option<usize>,
+    /// size of all images in bytes
+    pub size: Option<u64>,
+    pub rating: Option<f32>,
+    /// `namespace:tag`, e.g. `artist:name`
+    pub tags: Vec<String>,
+}
+
+/// gallery found by [`Client::list`]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ListedGallery {
+    pub url: String,
+    pub title: Option<String>,
+    /// lowercase, e.g. `english`, when the listing tells
+    pub language: Option<String>,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct FailedPage {
+    pub page: usize,
+    pub url: String,
+    pub error: Error,
+}
+
+impl FailedPage {
+    /// `page` is 1-based like in [`crate::PageRange`]
+    pub fn new(page: usize, url: impl Into<String>, error: Error) -> Self {
+        Self {
+            page,
+            url: url.into(),
+            error,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ResolvedPage {
+    pub page: usize,
+    pub url: String,
+    /// where the image would be saved
+    pub out: PathBuf,
+}
+
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct Summary {
+    pub metadata: Option<Metadata>,
+    /// pages that were scheduled
+    pub pages: usize,
+    pub failed: Vec<FailedPage>,
+    /// filled by [`GalleryHandle::resolve`] only, sorted by page
+    pub resolved: Vec<ResolvedPage>,
+    /// error that stopped the gallery before all pages were scheduled
+    pub error: Option<Error>,
+    /// the client was cancelled before all pages were scheduled
+    pub cancelled: bool,
+}
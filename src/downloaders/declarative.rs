@@ -0,0 +1,746 @@
+//! sites described by data instead of code, see [`SiteDefinition`]
+
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Context, Result};
+use hyper::Uri;
+use regex::Regex;
+use serde::Deserialize;
+use tl::{HTMLTag, Parser};
+use tokio::sync::mpsc::Sender;
+
+use crate::{
+    client::ListedGallery,
+    error::{Error, SelectorNotFound},
+    page_range::PageRange,
+    transport::Ctx,
+};
+
+use super::utils::common_url_pattern_donwloader::{CommonUrlPatternDownloader, GalleryInfo};
+use super::utils::{self, GetHtmlTag, TagWithParser};
+use super::{Downloader, ListingPage, Msg, Page, ParserTask};
+
+/// a site described in toml, overrides the built-in site of the same name
+///
+/// ```toml
+/// name = "Imhentai"
+/// authorities = ["imhentai.xxx"]
+/// path = '^/gallery/(?P<gallery_id>\d+)/?$'
+/// strategy = "url-pattern"
+///
+/// title = { select = ["div.right_details", "h1"] }
+/// pages = { select = ["div.right_details", "li.pages"], regex = 'Pages:\s+(\d+)' }
+/// first-image = { select = ["div#append_thumbs", "div.gthumb", "a"], attr = "href" }
+/// full-image = { select = ["div.gview", "img#gimg"], attr = "data-src" }
+/// ```
+///
+/// A field takes the first match of each selector in turn, or the last one with a
+/// `:last` suffix; its value is the `attr` attribute or the text of the element,
+/// narrowed to the first group of `regex`.
+///
+/// The `url-pattern` strategy reads `title`, `pages` and `first-image` from the
+/// gallery page and `full-image` from the first image page; the other pages are
+/// numbered like the first image. The `per-page` strategy reads `title`, every
+/// match of `page-links` and `next-page` from the gallery pages, and `full-image`
+/// from each image page.
+///
+/// Urls matching the optional `listing-path` are listings: every match of
+/// `listing-links` that is a gallery url is listed, and `listing-next` leads to
+/// the next listing page.
+#[derive(Clone)]
+pub struct SiteDefinition {
+    name: &'static str,
+    authorities: Vec<String>,
+    path_re: Regex,
+    strategy: Strategy,
+    title: Field,
+    full_image: Field,
+    listing: Option<Listing>,
+}
+
+#[derive(Clone)]
+struct Listing {
+    path_re: Regex,
+    links: Field,
+    next: Option<Field>,
+}
+
+#[derive(Clone)]
+enum Strategy {
+    UrlPattern { pages: Field, first_image: Field },
+    PerPage { links: Field, next: Option<Field> },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawDefinition {
+    name: String,
+    authorities: Vec<String>,
+    path: String,
+    strategy: RawStrategy,
+    title: RawField,
+    full_image: RawField,
+    pages: Option<RawField>,
+    first_image: Option<RawField>,
+    page_links: Option<RawField>,
+    next_page: Option<RawField>,
+    listing_path: Option<String>,
+    listing_links: Option<RawField>,
+    listing_next: Option<RawField>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum RawStrategy {
+    UrlPattern,
+    PerPage,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawField {
+    select: Vec<String>,
+    attr: Option<String>,
+    regex: Option<String>,
+}
+
+impl SiteDefinition {
+    pub fn from_toml(definition: &str) -> Result<Self, Error> {
+        Self::parse(definition).map_err(Error::from_anyhow)
+    }
+
+    fn parse(definition: &str) -> Result<Self> {
+        let raw: RawDefinition = toml::from_str(definition)?;
+
+        let field = |name: &str, raw: Option<RawField>| -> Result<Field> {
+            let raw = raw.with_context(|| format!("`{name}` is required by this strategy"))?;
+            Field::new(raw).with_context(|| format!("invalid `{name}`"))
+        };
+        let strategy = match raw.strategy {
+            RawStrategy::UrlPattern => Strategy::UrlPattern {
+                pages: field("pages", raw.pages)?,
+                first_image: field("first-image", raw.first_image)?,
+            },
+            RawStrategy::PerPage => Strategy::PerPage {
+                links: field("page-links", raw.page_links)?,
+                next: raw
+                    .next_page
+                    .map(|next| field("next-page", Some(next)))
+                    .transpose()?,
+            },
+        };
+        if raw.authorities.is_empty() {
+            bail!("`authorities` is empty");
+        }
+        let listing = match raw.listing_path {
+            Some(path) => Some(Listing {
+                path_re: Regex::new(&path).context("invalid `listing-path`")?,
+                links: field("listing-links", raw.listing_links)?,
+                next: raw
+                    .listing_next
+                    .map(|next| field("listing-next", Some(next)))
+                    .transpose()?,
+            }),
+            None if raw.listing_links.is_some() || raw.listing_next.is_some() => {
+                bail!("`listing-links` and `listing-next` need `listing-path`")
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            name: intern(raw.name),
+            authorities: raw.authorities,
+            path_re: Regex::new(&raw.path).context("invalid `path`")?,
+            strategy,
+            title: field("title", Some(raw.title))?,
+            full_image: field("full-image", Some(raw.full_image))?,
+            listing,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub(crate) fn downloader(self) -> Arc<dyn Downloader> {
+        match self.strategy.clone() {
+            Strategy::UrlPattern { pages, first_image } => Arc::new(PatternSite {
+                site: self,
+                pages,
+                first_image,
+            }),
+            Strategy::PerPage { links, next } => Arc::new(PagedSite {
+                site: self,
+                links,
+                next,
+            }),
+        }
+    }
+
+    fn is_gallery_match(&self, gallery: &Uri) -> bool {
+        utils::is_supported_scheme(gallery)
+            && self
+                .authorities
+                .iter()
+                .any(|authority| utils::is_proper_authority(gallery, authority))
+            && self.path_re.is_match(gallery.path())
+    }
+
+    fn parse_image_page(&self, page: &[u8]) -> Result<Uri> {
+        let page = String::from_utf8_lossy(page);
+        let dom = tl::parse(&page, Default::default())?;
+        let html = &dom.get_html_tag()?;
+
+        self.full_image.uri(html)
+    }
+
+    fn is_listing_match(&self, url: &Uri) -> bool {
+        utils::is_supported_scheme(url)
+            && self
+                .authorities
+                .iter()
+                .any(|authority| utils::is_proper_authority(url, authority))
+            && self
+                .listing
+                .as_ref()
+                .is_some_and(|listing| listing.path_re.is_match(url.path()))
+    }
+
+    async fn listing_page(&self, ctx: &Ctx, url: &Uri) -> Result<ListingPage> {
+        let page = ctx.page(url).await?;
+        ctx.parse(url, &page, |page| self.parse_listing_page(url, page))
+    }
+
+    /// gallery links only, the listing selector may match others too
+    fn parse_listing_page(&self, page_url: &Uri, page: &[u8]) -> Result<ListingPage> {
+        let listing = self
+            .listing
+            .as_ref()
+            .context("no listing in the definition")?;
+        let page = String::from_utf8_lossy(page);
+        let dom = tl::parse(&page, Default::default())?;
+        let html = &dom.get_html_tag()?;
+
+        let mut galleries = Vec::<ListedGallery>::new();
+        for tag in listing.links.all(html)? {
+            let Ok(link) = listing.links.value(tag, html.parser) else {
+                continue;
+            };
+            let url = utils::merge_uris(&Uri::try_from(link)?, page_url)?;
+            if !self.is_gallery_match(&url) {
+                continue;
+            }
+            let url = url.to_string();
+            if !galleries.iter().any(|gallery| gallery.url == url) {
+                galleries.push(ListedGallery {
+                    url,
+                    title: None,
+                    language: None,
+                });
+            }
+        }
+
+        // the last page has no link to the next one
+        let next = listing
+            .next
+            .as_ref()
+            .and_then(|next| next.uri(html).ok())
+            .map(|next| utils::merge_uris(&next, page_url))
+            .transpose()?;
+
+        Ok(ListingPage { galleries, next })
+    }
+}
+
+/// `name` as long lived as the names of the built-in sites; each name is leaked
+/// once however many times the definitions are loaded
+fn intern(name: String) -> &'static str {
+    static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+    let mut names = NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(name) = names.get(name.as_str()) {
+        return name;
+    }
+    let name = Box::leak(name.into_boxed_str());
+    names.insert(name);
+    name
+}
+
+/// selector step, `:last` takes the last match instead of the first
+#[derive(Clone)]
+struct Step {
+    selector: String,
+    last: bool,
+}
+
+#[derive(Clone)]
+struct Field {
+    steps: Vec<Step>,
+    attr: Option<String>,
+    regex: Option<Regex>,
+}
+
+impl Field {
+    fn new(raw: RawField) -> Result<Self> {
+        if raw.select.is_empty() {
+            bail!("`select` is empty");
+        }
+
+        Ok(Self {
+            steps: raw
+                .select
+                .into_iter()
+                .map(|selector| match selector.strip_suffix(":last") {
+                    Some(selector) => Step {
+                        selector: selector.to_owned(),
+                        last: true,
+                    },
+                    None => Step {
+                        selector,
+                        last: false,
+                    },
+                })
+                .collect(),
+            attr: raw.attr,
+            regex: raw.regex.map(|re| Regex::new(&re)).transpose()?,
+        })
+    }
+
+    /// the tag `steps` lead to, failing with the steps followed so far
+    fn select<'a, 'b>(html: &TagWithParser<'a, 'b>, steps: &'b [Step]) -> Result<&'b HTMLTag<'a>> {
+        steps
+            .iter()
+            .enumerate()
+            .try_fold(html.tag, |tag, (index, step)| {
+                step.select(tag, html.parser)?.ok_or_else(|| {
+                    let chain = steps[..=index]
+                        .iter()
+                        .map(Step::to_string)
+                        .collect::<Vec<_>>();
+                    SelectorNotFound(chain.join(" > ")).into()
+                })
+            })
+    }
+
+    /// every match of the last step
+    fn all<'a, 'b>(&'b self, html: &TagWithParser<'a, 'b>) -> Result<Vec<&'b HTMLTag<'a>>> {
+        let (last, steps) = self.steps.split_last().context("no selector")?;
+        let tag = Self::select(html, steps)?;
+
+        Ok(tag
+            .query_selector(html.parser, &last.selector)
+            .with_context(|| format!("failed to query selector: {:?}", last.selector))?
+            .filter_map(|node| node.get(html.parser))
+            .filter_map(|node| node.as_tag())
+            .collect())
+    }
+
+    fn value(&self, tag: &HTMLTag<'_>, parser: &Parser<'_>) -> Result<String> {
+        let value = match &self.attr {
+            Some(attr) => {
+                utils::attr(tag, attr).with_context(|| format!("no {attr:?} attribute"))?
+            }
+            None => tag.inner_text(parser).into_owned(),
+        };
+
+        match &self.regex {
+            Some(re) => re
+                .captures(&value)
+                .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+                .map(|m| m.as_str().to_owned())
+                .with_context(|| format!("{re} doesn't match {value:?}")),
+            None => Ok(value.trim().to_owned()),
+        }
+    }
+
+    fn text(&self, html: &TagWithParser<'_, '_>) -> Result<String> {
+        let tag = Self::select(html, &self.steps)?;
+        self.value(tag, html.parser)
+    }
+
+    fn uri(&self, html: &TagWithParser<'_, '_>) -> Result<Uri> {
+        Uri::try_from(self.text(html)?).context("failed to create uri")
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.selector)?;
+        if self.last {
+            f.write_str(":last")?;
+        }
+        Ok(())
+    }
+}
+
+impl Step {
+    /// `None` if nothing matches
+    fn select<'a, 'b>(
+        &'b self,
+        tag: &'b HTMLTag<'a>,
+        parser: &'b Parser<'a>,
+    ) -> Result<Option<&'b HTMLTag<'a>>> {
+        let mut query = tag
+            .query_selector(parser, &self.selector)
+            .with_context(|| format!("failed to query selector: {:?}", self.selector))?;
+        let node = if self.last {
+            query.last()
+        } else {
+            query.next()
+        };
+
+        Ok(node
+            .and_then(|node| node.get(parser))
+            .and_then(|node| node.as_tag()))
+    }
+}
+
+/// [`CommonUrlPatternDownloader`] of a definition
+struct PatternSite {
+    site: SiteDefinition,
+    pages: Field,
+    first_image: Field,
+}
+
+impl CommonUrlPatternDownloader for PatternSite {
+    fn get_info(&self, html: &TagWithParser<'_, '_>) -> Result<GalleryInfo> {
+        let title = self.site.title.text(html)?;
+        let pages_count = self
+            .pages
+            .text(html)?
+            .parse()
+            .context("failed to parse pages count")?;
+
+        Ok(GalleryInfo { pages_count, title })
+    }
+
+    fn get_first_image_url(&self, html: &TagWithParser<'_, '_>) -> Result<Uri> {
+        self.first_image.uri(html)
+    }
+
+    fn parse_first_image_page(&self, page: &[u8]) -> Result<Uri> {
+        self.site.parse_image_page(page)
+    }
+}
+
+#[async_trait::async_trait]
+impl Downloader for PatternSite {
+    fn name(&self) -> &'static str {
+        self.site.name
+    }
+
+    fn is_listing_match(&self, url: &Uri) -> bool {
+        self.site.is_listing_match(url)
+    }
+
+    async fn listing_page(&self, ctx: &Ctx, url: &Uri) -> Result<ListingPage> {
+        self.site.listing_page(ctx, url).await
+    }
+
+    fn is_gallery_match(&self, gallery: &Uri) -> bool {
+        self.site.is_gallery_match(gallery)
+    }
+
+    fn gallery_id(&self, gallery: &Uri) -> Option<String> {
+        utils::capture_gallery_id(&self.site.path_re, gallery)
+    }
+}
+
+/// gallery pages list the image pages, each one resolved on its own
+struct PagedSite {
+    site: SiteDefinition,
+    links: Field,
+    next: Option<Field>,
+}
+
+impl PagedSite {
+    /// returns: (title, image page urls, next gallery page url)
+    fn parse_gallery_page(
+        &self,
+        page_url: &Uri,
+        page: &[u8],
+    ) -> Result<(String, Vec<Uri>, Option<Uri>)> {
+        let page = String::from_utf8_lossy(page);
+        let dom = tl::parse(&page, Default::default())?;
+        let html = &dom.get_html_tag()?;
+
+        let urls = self
+            .links
+            .all(html)?
+            .into_iter()
+            .map(|tag| {
+                let url = Uri::try_from(self.links.value(tag, html.parser)?)?;
+                utils::merge_uris(&url, page_url)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // the last gallery page has no link to the next one
+        let next = self
+            .next
+            .as_ref()
+            .and_then(|next| next.uri(html).ok())
+            .map(|next| utils::merge_uris(&next, page_url))
+            .transpose()?;
+
+        Ok((self.site.title.text(html)?, urls, next))
+    }
+}
+
+#[async_trait::async_trait]
+impl Downloader for PagedSite {
+    fn name(&self) -> &'static str {
+        self.site.name
+    }
+
+    fn is_listing_match(&self, url: &Uri) -> bool {
+        self.site.is_listing_match(url)
+    }
+
+    async fn listing_page(&self, ctx: &Ctx, url: &Uri) -> Result<ListingPage> {
+        self.site.listing_page(ctx, url).await
+    }
+
+    fn is_gallery_match(&self, gallery: &Uri) -> bool {
+        self.site.is_gallery_match(gallery)
+    }
+
+    fn gallery_id(&self, gallery: &Uri) -> Option<String> {
+        utils::capture_gallery_id(&self.site.path_re, gallery)
+    }
+
+    async fn resolve_image_url<'a>(&self, ctx: &Ctx, url: &'a Uri) -> Result<Cow<'a, Uri>> {
+        let page = ctx.page(url).await?;
+        let image = ctx.parse(url, &page, |page| {
+            utils::merge_uris(&self.site.parse_image_page(page)?, url)
+        })?;
+
+        Ok(Cow::Owned(image))
+    }
+}
+
+#[async_trait::async_trait]
+impl ParserTask for PagedSite {
+    async fn try_start_parser_task(
+        self: Arc<Self>,
+        ctx: Ctx,
+        tx: Sender<Msg>,
+        gallery: Arc<Uri>,
+        pages: Arc<PageRange>,
+    ) -> Result<()> {
+        let mut page_url = gallery.as_ref().clone();
+        let mut index = 1;
+
+        loop {
+            let page = ctx.page(&page_url).await?;
+            let (title, page_urls, next) = ctx.parse(&page_url, &page, |page| {
+                self.parse_gallery_page(&page_url, page)
+            })?;
+
+            if index == 1 {
+                tx.send(Msg::Title(title)).await?;
+            }
+
+            let first = index;
+            index += page_urls.len();
+            let page_urls = page_urls
+                .into_iter()
+                .zip(first..)
+                .filter(|(_, index)| pages.contains(*index))
+                .map(|(url, index)| Page {
+                    index,
+                    url,
+                    image: None,
+                })
+                .collect();
+            tx.send(Msg::Images(page_urls)).await?;
+
+            match next {
+                // a page without images would loop forever
+                Some(_) if index == first || pages.is_past(index) => break,
+                Some(next) => page_url = next,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloaders::fixture;
+
+    const IMHENTAI: &str = r#"
+        name = "Imhentai"
+        authorities = ["imhentai.xxx"]
+        path = '^/gallery/(?P<gallery_id>\d+)/?$'
+        strategy = "url-pattern"
+
+        title = { select = ["div.right_details", "h1"] }
+        pages = { select = ["div.right_details", "li.pages"], regex = 'Pages:\s+(\d+)' }
+        first-image = { select = ["div#append_thumbs", "div.gthumb", "a"], attr = "href" }
+        full-image = { select = ["div.gview", "img#gimg"], attr = "data-src" }
+
+        listing-path = '^/(artist|tag)/[^/]+/?$'
+        listing-links = { select = ["div.galleries", "a"], attr = "href" }
+        listing-next = { select = ["ul.pagination", "a.next"], attr = "href" }
+    "#;
+
+    const EHENTAI: &str = r#"
+        name = "Ehentai"
+        authorities = ["e-hentai.org"]
+        path = '^/g/(?P<gallery_id>\d+)/[[:xdigit:]]+/?$'
+        strategy = "per-page"
+
+        title = { select = ["div.gm", "h1#gn"] }
+        page-links = { select = ["div#gdt", "a"], attr = "href" }
+        next-page = { select = ["div.gtb", "table", "td:last", "a"], attr = "href" }
+        full-image = { select = ["img#img"], attr = "src" }
+    "#;
+
+    fn pattern_site(definition: &str) -> PatternSite {
+        let site = SiteDefinition::from_toml(definition).unwrap();
+        let Strategy::UrlPattern { pages, first_image } = site.strategy.clone() else {
+            panic!("not a url-pattern site");
+        };
+        PatternSite {
+            site,
+            pages,
+            first_image,
+        }
+    }
+
+    fn paged_site(definition: &str) -> PagedSite {
+        let site = SiteDefinition::from_toml(definition).unwrap();
+        let Strategy::PerPage { links, next } = site.strategy.clone() else {
+            panic!("not a per-page site");
+        };
+        PagedSite { site, links, next }
+    }
+
+    #[test]
+    fn url_pattern_site_parses_fixtures() {
+        let site = pattern_site(IMHENTAI);
+        let gallery = Uri::from_static("https://imhentai.xxx/gallery/1234567/");
+
+        assert!(site.is_gallery_match(&gallery));
+        assert_eq!(site.gallery_id(&gallery).as_deref(), Some("1234567"));
+
+        let (title, pages, first_image) = site
+            .parse_gallery_page(&gallery, &fixture("imhentai/gallery.html"))
+            .unwrap();
+        assert_eq!(title, "Sample Gallery");
+        assert_eq!(pages, 18);
+        assert_eq!(first_image, "https://imhentai.xxx/view/1234567/1/");
+
+        let image = site
+            .parse_first_image_page(&fixture("imhentai/image.html"))
+            .unwrap();
+        assert_eq!(image, "https://m7.imhentai.xxx/021/abcdef0123/1.webp");
+
+        // attributes are decoded like in the built-in sites
+        let image = site
+            .parse_first_image_page(&fixture("imhentai/image_signed.html"))
+            .unwrap();
+        assert_eq!(image.query(), Some("token=0a1b2c&expires=1700000000"));
+    }
+
+    #[test]
+    fn per_page_site_parses_fixtures() {
+        let site = paged_site(EHENTAI);
+        let gallery = Uri::from_static("https://e-hentai.org/g/1234567/0123456789/");
+
+        let (title, urls, next) = site
+            .parse_gallery_page(&gallery, &fixture("ehentai/gallery.html"))
+            .unwrap();
+        assert_eq!(title, "[Example Circle] Sample Gallery [English]");
+        assert_eq!(urls.len(), 20);
+        assert_eq!(
+            next.unwrap(),
+            "https://e-hentai.org/g/1234567/0123456789/?p=1"
+        );
+
+        let (_, urls, next) = site
+            .parse_gallery_page(&gallery, &fixture("ehentai/gallery_last.html"))
+            .unwrap();
+        assert_eq!(urls.len(), 5);
+        assert!(next.is_none());
+
+        let image = site
+            .site
+            .parse_image_page(&fixture("ehentai/image.html"))
+            .unwrap();
+        assert!(image.path().ends_with("/001.jpg"));
+    }
+
+    #[test]
+    fn lists_gallery_links() {
+        let site = pattern_site(IMHENTAI);
+        let artist = Uri::from_static("https://imhentai.xxx/artist/example-artist/");
+        assert!(site.is_listing_match(&artist));
+        assert!(!site.is_listing_match(&Uri::from_static("https://imhentai.xxx/gallery/1/")));
+        assert!(!paged_site(EHENTAI).is_listing_match(&artist));
+
+        let listing = site
+            .site
+            .parse_listing_page(&artist, &fixture("imhentai/listing.html"))
+            .unwrap();
+        // the flags, categories and repeated title links are left out
+        let urls = listing
+            .galleries
+            .iter()
+            .map(|gallery| gallery.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://imhentai.xxx/gallery/1234569/",
+                "https://imhentai.xxx/gallery/1234568/",
+                "https://imhentai.xxx/gallery/1234567/",
+            ]
+        );
+        assert_eq!(
+            listing.next.unwrap(),
+            "https://imhentai.xxx/artist/example-artist/?page=2"
+        );
+    }
+
+    #[test]
+    fn rejects_incomplete_definitions() {
+        let without_pages = IMHENTAI
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("pages"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(SiteDefinition::from_toml(&without_pages).is_err());
+
+        let unknown_strategy = EHENTAI.replace("per-page", "guess");
+        assert!(SiteDefinition::from_toml(&unknown_strategy).is_err());
+
+        let bad_regex = IMHENTAI.replace(r"Pages:\s+(\d+)", "Pages: (");
+        assert!(SiteDefinition::from_toml(&bad_regex).is_err());
+
+        let links_without_path = IMHENTAI
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("listing-path"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(SiteDefinition::from_toml(&links_without_path).is_err());
+    }
+
+    #[test]
+    fn reuses_the_names_of_reloaded_definitions() {
+        let first = SiteDefinition::from_toml(EHENTAI).unwrap();
+        let again = SiteDefinition::from_toml(EHENTAI).unwrap();
+        assert!(std::ptr::eq(first.name(), again.name()));
+
+        let renamed = SiteDefinition::from_toml(&EHENTAI.replace("Ehentai", "Mirror")).unwrap();
+        assert_eq!(renamed.name(), "Mirror");
+    }
+}
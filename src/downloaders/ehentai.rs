@@ -1,17 +1,35 @@
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use hyper::Uri;
 use regex::Regex;
-use tokio::sync::{mpsc::Sender, OnceCell};
-
-use crate::{downloaders::Downloader, request::request};
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+use tokio::time::timeout;
+use tracing::debug;
+
+use crate::{
+    client::GalleryDetails,
+    client::ListedGallery,
+    downloaders::{Downloader, ListingPage},
+    error::{self, ErrorKind, SelectorNotFound},
+    page_range::PageRange,
+    transport::Ctx,
+};
 
 use super::{
-    utils::{self, CollectResponse, GetHtmlTag, TagWithParser},
-    Msg, ParserTask,
+    utils::{self, GetHtmlTag, Ordered, TagWithParser},
+    CollectResponse, Msg, Page, ParserTask,
 };
 
+/// galleries per call of the gdata api, its limit
+const GDATA_BATCH: usize = 25;
+
+/// listing pages fetched ahead of the one whose images are queued
+const LISTINGS_AHEAD: usize = 2;
+/// image pages resolved ahead of the downloads, on top of the download jobs
+const RESOLVE_AHEAD: usize = 4;
+
 pub struct Ehentai {
     name: &'static str,
     authority: &'static str,
@@ -24,7 +42,84 @@ pub struct Ehentai {
     next_page_selector: &'static [&'static str],
     title_selector: &'static [&'static str],
 
+    showing_selector: &'static str,
+    showing_re: Regex,
+
+    newer_selector: &'static str,
+
     image_selector: &'static str,
+
+    api_url: &'static str,
+
+    listing_re: Regex,
+    listing_selector: &'static str,
+    listing_row_selector: &'static str,
+    listing_link_selector: &'static str,
+    listing_title_selector: &'static str,
+    listing_tag_selector: &'static str,
+    listing_next_selector: &'static str,
+}
+
+/// answer of the gdata api, see <https://ehwiki.org/wiki/API>
+#[derive(Deserialize)]
+struct Gdata {
+    #[serde(default)]
+    gmetadata: Vec<Gmetadata>,
+    error: Option<String>,
+}
+
+/// numbers are strings in the api, except for `filesize`
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Gmetadata {
+    gid: u64,
+    error: Option<String>,
+    title_jpn: String,
+    category: String,
+    uploader: String,
+    posted: String,
+    filecount: String,
+    filesize: Option<u64>,
+    rating: String,
+    tags: Vec<String>,
+}
+
+impl From<Gmetadata> for GalleryDetails {
+    fn from(gallery: Gmetadata) -> Self {
+        let text = |text: String| Some(text).filter(|text| !text.is_empty());
+        GalleryDetails {
+            title_original: text(gallery.title_jpn),
+            category: text(gallery.category),
+            uploader: text(gallery.uploader),
+            posted: gallery.posted.parse().ok(),
+            pages: gallery.filecount.parse().ok(),
+            size: gallery.filesize,
+            rating: gallery.rating.parse().ok(),
+            tags: gallery.tags,
+        }
+    }
+}
+
+/// one page of the gallery listing
+struct Listing {
+    /// only parsed when asked for
+    title: Option<String>,
+    /// image pages
+    urls: Vec<Uri>,
+    next: Option<Uri>,
+    /// (index of the first image, images in the gallery)
+    showing: Option<(usize, usize)>,
+}
+
+/// `p`th (0-based) listing page of `gallery`
+fn listing_url(gallery: &Uri, p: usize) -> Result<Uri> {
+    let scheme = gallery.scheme_str().unwrap_or("https");
+    let authority = gallery.authority().context("gallery url without a host")?;
+
+    Ok(Uri::try_from(format!(
+        "{scheme}://{authority}{}?p={p}",
+        gallery.path()
+    ))?)
 }
 
 impl Ehentai {
@@ -42,10 +137,77 @@ impl Ehentai {
             next_page_selector: &["div.gtb", "table"],
             title_selector: &["div.gm", "h1#gn"],
 
+            showing_selector: "p.gpc",
+            showing_re: Regex::new(r"Showing ([\d,]+) - [\d,]+ of ([\d,]+) images").unwrap(),
+
+            newer_selector: "div#gnd",
+
             image_selector: "img#img",
+
+            api_url: "https://api.e-hentai.org/api.php",
+
+            listing_re: Regex::new(r"^/(?:tag|uploader)/[^/]+/?$").unwrap(),
+            listing_selector: "table.itg",
+            listing_row_selector: "tr",
+            listing_link_selector: "td.glname",
+            listing_title_selector: "div.glink",
+            listing_tag_selector: "div.gt",
+            listing_next_selector: "a#dnext",
         }
     }
 
+    /// `/?f_search=...`, `/tag/...` and `/uploader/...`
+    fn is_listing_path_match(&self, url: &Uri) -> bool {
+        let search = url.path() == "/"
+            && url
+                .query()
+                .is_some_and(|query| query.split('&').any(|arg| arg.starts_with("f_search=")));
+        search || self.listing_re.is_match(url.path())
+    }
+
+    fn parse_listing_page(&self, page: &[u8]) -> Result<ListingPage> {
+        let page = String::from_utf8_lossy(page);
+        let dom = tl::parse(&page, Default::default())?;
+        let html = &dom.get_html_tag()?;
+
+        let table = utils::first_tag(html.tag, html.parser, self.listing_selector)
+            .ok_or_else(|| SelectorNotFound(self.listing_selector.to_owned()))?;
+        let galleries = utils::all_tags(table, html.parser, self.listing_row_selector)
+            .into_iter()
+            // the header row has no gallery
+            .filter_map(|row| utils::first_tag(row, html.parser, self.listing_link_selector))
+            .map(|name| {
+                let href = utils::first_tag(name, html.parser, "a")
+                    .and_then(|link| utils::attr(link, "href"))
+                    .with_context(|| format!("no link in {}", self.listing_link_selector))?;
+                let url = Uri::try_from(href)?;
+                let title = utils::first_tag(name, html.parser, self.listing_title_selector)
+                    .map(|title| title.inner_text(html.parser).trim().to_owned());
+                // the tags shown are a few only, the title usually tells too
+                let language = utils::all_tags(name, html.parser, self.listing_tag_selector)
+                    .into_iter()
+                    .filter_map(|tag| utils::attr(tag, "title"))
+                    .filter_map(|tag| Some(tag.strip_prefix("language:")?.to_owned()))
+                    .find(|language| language != "translated" && language != "rewrite")
+                    .or_else(|| title.as_deref().and_then(utils::title_language));
+
+                Ok(ListedGallery {
+                    url: url.to_string(),
+                    title,
+                    language,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // a `span` on the last page
+        let next = utils::first_tag(html.tag, html.parser, self.listing_next_selector)
+            .and_then(|next| utils::attr(next, "href"))
+            .map(Uri::try_from)
+            .transpose()?;
+
+        Ok(ListingPage { galleries, next })
+    }
+
     #[inline]
     fn is_gallery_path_match(&self, uri: &Uri) -> bool {
         self.path_re.is_match(uri.path())
@@ -81,14 +243,59 @@ impl Ehentai {
         Ok(name.to_string())
     }
 
-    /// returns: (title, urls, next page url)
-    async fn get_page_img_urls(
-        &self,
-        need_name: bool,
-        page_url: &Uri,
-    ) -> Result<(Option<String>, Vec<Uri>, Option<Uri>)> {
-        let page = request(page_url).await?.collect_response().await?;
-        let page = String::from_utf8_lossy(&page);
+    /// `Showing 21 - 40 of 45 images`: (index of the first image, images in the gallery)
+    fn get_showing(&self, html: &TagWithParser<'_, '_>) -> Option<(usize, usize)> {
+        let text = html
+            .query_selector(self.showing_selector)
+            .and_then(|mut q| q.next())
+            .and_then(|node| node.get(html.parser))?
+            .inner_text(html.parser);
+        let captures = self.showing_re.captures(&text)?;
+        let number = |group| captures.get(group)?.as_str().replace(',', "").parse().ok();
+
+        Some((number(1)?, number(2)?))
+    }
+
+    /// last link of `There are newer versions of this gallery available`, the newest
+    fn parse_newer_version(&self, page: &[u8]) -> Result<Option<Uri>> {
+        let page = String::from_utf8_lossy(page);
+
+        let dom = tl::parse(&page, Default::default())?;
+        let html = &dom.get_html_tag()?;
+        // every gallery page has a title, the block is optional
+        self.get_title(html)?;
+
+        let Some(newer) = html
+            .query_selector(self.newer_selector)
+            .and_then(|mut q| q.next())
+            .and_then(|node| node.get(html.parser))
+            .and_then(|node| node.as_tag())
+        else {
+            return Ok(None);
+        };
+        let href = newer
+            .query_selector(html.parser, "a")
+            .and_then(|q| q.last())
+            .and_then(|node| node.get(html.parser))
+            .and_then(|node| node.as_tag())
+            .and_then(|tag| tag.attributes().get("href"))
+            .flatten()
+            .with_context(|| format!("no gallery link in {}", self.newer_selector))?;
+        let url = Uri::try_from(href.as_bytes())?;
+        if !self.is_gallery_match(&url) {
+            bail!("newer version isn't a gallery: {url}");
+        }
+
+        Ok(Some(url))
+    }
+
+    async fn get_listing(&self, ctx: &Ctx, need_name: bool, page_url: &Uri) -> Result<Listing> {
+        let page = ctx.page(page_url).await?;
+        ctx.parse(page_url, &page, |page| self.parse_listing(need_name, page))
+    }
+
+    fn parse_listing(&self, need_name: bool, page: &[u8]) -> Result<Listing> {
+        let page = String::from_utf8_lossy(page);
 
         let dom = tl::parse(&page, Default::default())?;
         let html = &dom.get_html_tag()?;
@@ -98,7 +305,7 @@ impl Ehentai {
             .and_then(|mut q| q.next())
             .and_then(|node| node.get(html.parser))
             .and_then(|node| node.as_tag())
-            .with_context(|| format!("selector not found: {}", self.gallery_selector))?;
+            .ok_or_else(|| SelectorNotFound(self.gallery_selector.to_owned()))?;
 
         let urls = gallery
             .query_selector(html.parser, self.gallery_link_selector)
@@ -120,13 +327,36 @@ impl Ehentai {
             .with_context(|| format!("failed to query selector: {}", self.gallery_link_selector))?
             .collect::<Result<Vec<_>>>()?;
 
-        let next = self.get_next_page_url(html);
-        let name = if need_name {
+        let title = if need_name {
             Some(self.get_title(html)?)
         } else {
             None
         };
-        Ok((name, urls, next))
+        Ok(Listing {
+            title,
+            urls,
+            next: self.get_next_page_url(html),
+            showing: self.get_showing(html),
+        })
+    }
+
+    fn parse_image_page(&self, page: &[u8]) -> Result<Uri> {
+        let page = String::from_utf8_lossy(page);
+
+        let dom = tl::parse(&page, Default::default())?;
+        let parser = dom.parser();
+
+        let img = dom
+            .query_selector(self.image_selector)
+            .and_then(|mut q| q.next())
+            .and_then(|node| node.get(parser))
+            .and_then(|node| node.as_tag())
+            .map(|tag| tag.attributes())
+            .and_then(|attrs| attrs.get("src"))
+            .with_context(|| format!("failed to query selector: {}", self.image_selector))?
+            .context("empty 'src' attribute")?;
+
+        Ok(Uri::try_from(img.as_bytes())?)
     }
 }
 
@@ -142,26 +372,154 @@ impl Downloader for Ehentai {
             && self.is_gallery_path_match(gallery)
     }
 
-    async fn resolve_image_url<'a>(&self, url: &'a Uri) -> Result<Cow<'a, Uri>> {
-        let page = request(url).await?.collect_response().await?;
-        let page = String::from_utf8_lossy(&page);
+    fn gallery_id(&self, gallery: &Uri) -> Option<String> {
+        utils::capture_gallery_id(&self.path_re, gallery)
+    }
 
-        let dom = tl::parse(&page, Default::default())?;
-        let parser = dom.parser();
+    async fn resolve_image_url<'a>(&self, ctx: &Ctx, url: &'a Uri) -> Result<Cow<'a, Uri>> {
+        let page = ctx.page(url).await?;
+        let url = ctx.parse(url, &page, |page| self.parse_image_page(page))?;
 
-        let img = dom
-            .query_selector(self.image_selector)
-            .and_then(|mut q| q.next())
-            .and_then(|node| node.get(parser))
-            .and_then(|node| node.as_tag())
-            .map(|tag| tag.attributes())
-            .and_then(|attrs| attrs.get("src"))
-            .with_context(|| format!("failed to query selector: {}", self.image_selector))?
-            .context("empty 'src' attribute")?;
+        Ok(Cow::Owned(url))
+    }
 
-        let url = Uri::try_from(img.as_bytes())?;
+    fn is_listing_match(&self, url: &Uri) -> bool {
+        utils::is_supported_scheme(url)
+            && utils::is_proper_authority(url, self.authority)
+            && self.is_listing_path_match(url)
+    }
 
-        Ok(Cow::Owned(url))
+    async fn listing_page(&self, ctx: &Ctx, url: &Uri) -> Result<ListingPage> {
+        let page = ctx.page(url).await?;
+        ctx.parse(url, &page, |page| self.parse_listing_page(page))
+    }
+
+    async fn newer_version(&self, ctx: &Ctx, gallery: &Uri) -> Result<Option<Uri>> {
+        let page = ctx.page(gallery).await?;
+        ctx.parse(gallery, &page, |page| self.parse_newer_version(page))
+    }
+
+    fn details_batch(&self) -> usize {
+        GDATA_BATCH
+    }
+
+    async fn details(&self, ctx: &Ctx, galleries: &[Uri]) -> Result<Vec<GalleryDetails>> {
+        // (gid, token) from the `/g/<gid>/<token>/` urls
+        let ids = galleries
+            .iter()
+            .map(|gallery| {
+                let captures = self.path_re.captures(gallery.path())?;
+                let gid = captures.name("gallery_id")?.as_str().parse::<u64>().ok()?;
+                let token = captures.name("gellery_hex")?.as_str();
+                Some((gid, token))
+            })
+            .collect::<Vec<_>>();
+        let gidlist = ids.iter().flatten().collect::<Vec<_>>();
+        if gidlist.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let api = Uri::from_static(self.api_url);
+        let request = serde_json::json!({
+            "method": "gdata",
+            "gidlist": gidlist,
+            "namespace": 1,
+        });
+        let response = ctx
+            .post(&api, "application/json", request.to_string().into())
+            .await?;
+        let status = response.status();
+        let body = response.collect_response().await?;
+        if !status.is_success() {
+            return Err(error::status_error(&api, status, &body));
+        }
+        let gdata = serde_json::from_slice::<Gdata>(&body)
+            .context("invalid gdata response")
+            .context(ErrorKind::Parse { selector: None })?;
+        if let Some(e) = gdata.error {
+            bail!("gdata api: {e}");
+        }
+
+        let mut found = gdata
+            .gmetadata
+            .into_iter()
+            .map(|gallery| (gallery.gid, gallery))
+            .collect::<HashMap<_, _>>();
+        let details = ids
+            .into_iter()
+            .map(|id| match id.and_then(|(gid, _)| found.remove(&gid)) {
+                Some(Gmetadata {
+                    gid,
+                    error: Some(e),
+                    ..
+                }) => {
+                    debug!(gid, error = e, "gdata api refused gallery");
+                    GalleryDetails::default()
+                }
+                Some(gallery) => gallery.into(),
+                None => GalleryDetails::default(),
+            })
+            .collect();
+
+        Ok(details)
+    }
+}
+
+impl Ehentai {
+    /// queues the selected pages of `urls`, numbered from `first`,
+    /// with their image urls resolved ahead
+    async fn queue(
+        self: &Arc<Self>,
+        ctx: &Ctx,
+        tx: &Sender<Msg>,
+        resolving: &mut Ordered<Page>,
+        pages: &PageRange,
+        first: usize,
+        urls: Vec<Uri>,
+    ) -> Result<()> {
+        let selected = urls
+            .into_iter()
+            .zip(first..)
+            .filter(|(_, index)| pages.contains(*index));
+        for (url, index) in selected {
+            while resolving.is_full() {
+                send_next(tx, resolving).await?;
+            }
+
+            let this = Arc::clone(self);
+            let ctx = ctx.clone();
+            resolving.push(async move {
+                let resolve = this.resolve_image_url(&ctx, &url);
+                let image = match timeout(ctx.site().read_timeout(), resolve).await {
+                    Ok(Ok(image)) => Some(image.into_owned()),
+                    // the download resolves it again, with retries and error reporting
+                    Ok(Err(e)) => {
+                        debug!(
+                            page = index,
+                            error = format!("{e:#}"),
+                            "failed to resolve ahead"
+                        );
+                        None
+                    }
+                    Err(_) => None,
+                };
+
+                Page { index, url, image }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// sends the oldest resolved page, `false` if there is none
+async fn send_next(tx: &Sender<Msg>, resolving: &mut Ordered<Page>) -> Result<bool> {
+    match resolving.next().await {
+        Some(page) => {
+            tx.send(Msg::Images(vec![page?])).await?;
+            Ok(true)
+        }
+        None => Ok(false),
     }
 }
 
@@ -169,31 +527,274 @@ impl Downloader for Ehentai {
 impl ParserTask for Ehentai {
     async fn try_start_parser_task(
         self: Arc<Self>,
+        ctx: Ctx,
         tx: Sender<Msg>,
         gallery: Arc<Uri>,
+        pages: Arc<PageRange>,
     ) -> Result<()> {
-        let mut page_url = Cow::Borrowed(gallery.as_ref());
+        let first = self.get_listing(&ctx, true, &gallery).await?;
+        if let Some(title) = first.title {
+            tx.send(Msg::Title(title)).await?;
+        }
 
-        let once = OnceCell::new();
-        loop {
-            let need_name = !once.initialized();
-            let (title, page_urls, next) = self.get_page_img_urls(need_name, &page_url).await?;
+        if let Some((_, total)) = first.showing {
+            tx.send(Msg::PageCount(total)).await?;
+        }
 
-            once.get_or_try_init(|| async {
-                let title = title.expect("no title parsed");
-                tx.send(Msg::Title(title)).await
-            })
+        let mut resolving = Ordered::new(RESOLVE_AHEAD);
+        let per_page = first.urls.len();
+        self.queue(&ctx, &tx, &mut resolving, &pages, 1, first.urls)
             .await?;
 
-            tx.send(Msg::Images(page_urls)).await?;
-
-            if let Some(next) = next {
-                page_url = Cow::Owned(next);
-            } else {
-                break;
+        match first.showing {
+            // the other listing pages are known from the count, fetch them ahead
+            Some((_, total)) if per_page > 0 => {
+                let mut listings = (1..total.div_ceil(per_page)).filter(|p| {
+                    let first = p * per_page + 1;
+                    (first..first + per_page).any(|index| pages.contains(index))
+                });
+                let mut ahead = Ordered::new(LISTINGS_AHEAD);
+                loop {
+                    while !ahead.is_full() {
+                        let Some(p) = listings.next() else {
+                            break;
+                        };
+                        let url = listing_url(&gallery, p)?;
+                        let this = Arc::clone(&self);
+                        let ctx = ctx.clone();
+                        ahead.push(async move {
+                            let listing = this.get_listing(&ctx, false, &url).await?;
+                            anyhow::Ok((p, listing))
+                        });
+                    }
+
+                    let Some(listing) = ahead.next().await else {
+                        break;
+                    };
+                    let (p, listing) = listing??;
+                    let first = listing.showing.map_or(p * per_page + 1, |(first, _)| first);
+                    self.queue(&ctx, &tx, &mut resolving, &pages, first, listing.urls)
+                        .await?;
+                }
+            }
+            // no count, follow the links one listing page after another
+            _ => {
+                let mut index = 1 + per_page;
+                let mut next = first.next;
+                while let Some(url) = next {
+                    // no need to walk the listing past the selected pages
+                    if per_page == 0 || pages.is_past(index) {
+                        break;
+                    }
+                    let listing = self.get_listing(&ctx, false, &url).await?;
+                    let count = listing.urls.len();
+                    self.queue(&ctx, &tx, &mut resolving, &pages, index, listing.urls)
+                        .await?;
+                    if count == 0 {
+                        break;
+                    }
+                    index += count;
+                    next = listing.next;
+                }
             }
         }
 
+        while send_next(&tx, &mut resolving).await? {}
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloaders::fixture;
+
+    #[test]
+    fn matches_gallery_urls() {
+        let ehentai = Ehentai::new();
+        let gallery = Uri::from_static("https://e-hentai.org/g/1234567/0123456789/");
+
+        assert!(ehentai.is_gallery_match(&gallery));
+        assert!(!ehentai.is_gallery_match(&Uri::from_static(
+            "https://e-hentai.org/s/0000001ed3/1234567-1"
+        )));
+        assert!(
+            !ehentai.is_gallery_match(&Uri::from_static("https://e-hentai.org/g/1234567/not-hex/"))
+        );
+        assert_eq!(ehentai.gallery_id(&gallery).as_deref(), Some("1234567"));
+    }
+
+    #[test]
+    fn parses_first_gallery_page() {
+        let listing = Ehentai::new()
+            .parse_listing(true, &fixture("ehentai/gallery.html"))
+            .unwrap();
+
+        assert_eq!(
+            listing.title.as_deref(),
+            Some("[Example Circle] Sample Gallery [English]")
+        );
+        assert_eq!(listing.urls.len(), 20);
+        assert_eq!(
+            listing.urls[0],
+            "https://e-hentai.org/s/0000001eef/1234567-1"
+        );
+        assert_eq!(
+            listing.urls[19],
+            "https://e-hentai.org/s/0000026aac/1234567-20"
+        );
+        assert_eq!(
+            listing.next.unwrap(),
+            "https://e-hentai.org/g/1234567/0123456789/?p=1"
+        );
+        assert_eq!(listing.showing, Some((1, 45)));
+    }
+
+    #[test]
+    fn parses_last_gallery_page() {
+        let listing = Ehentai::new()
+            .parse_listing(false, &fixture("ehentai/gallery_last.html"))
+            .unwrap();
+
+        assert!(listing.title.is_none());
+        assert_eq!(listing.urls.len(), 5);
+        assert_eq!(
+            listing.urls[4],
+            "https://e-hentai.org/s/0000057003/1234567-45"
+        );
+        assert!(listing.next.is_none());
+        assert_eq!(listing.showing, Some((41, 45)));
+    }
+
+    #[test]
+    fn parses_image_page() {
+        let url = Ehentai::new()
+            .parse_image_page(&fixture("ehentai/image.html"))
+            .unwrap();
+
+        assert_eq!(url.host(), Some("abcdefg.hijklmnop.hath.network"));
+        assert!(url.path().ends_with("/001.jpg"));
+    }
+
+    #[test]
+    fn rejects_other_pages() {
+        let ehentai = Ehentai::new();
+
+        assert!(ehentai
+            .parse_listing(true, &fixture("ehentai/image.html"))
+            .is_err());
+        assert!(ehentai
+            .parse_image_page(&fixture("ehentai/gallery.html"))
+            .is_err());
+    }
+
+    #[test]
+    fn finds_newest_version() {
+        let ehentai = Ehentai::new();
+
+        assert_eq!(
+            ehentai
+                .parse_newer_version(&fixture("ehentai/gallery_updated.html"))
+                .unwrap()
+                .unwrap(),
+            "https://e-hentai.org/g/1234567/0123456789/"
+        );
+        assert!(ehentai
+            .parse_newer_version(&fixture("ehentai/gallery.html"))
+            .unwrap()
+            .is_none());
+        assert!(ehentai
+            .parse_newer_version(&fixture("ehentai/image.html"))
+            .is_err());
+    }
+
+    #[test]
+    fn parses_search_results() {
+        let ehentai = Ehentai::new();
+
+        for url in [
+            "https://e-hentai.org/?f_search=example+circle",
+            "https://e-hentai.org/?f_cats=0&f_search=artist%3Aexample",
+            "https://e-hentai.org/tag/artist:example+artist",
+            "https://e-hentai.org/uploader/uploader",
+        ] {
+            assert!(ehentai.is_listing_match(&Uri::from_static(url)), "{url}");
+        }
+        assert!(!ehentai.is_listing_match(&Uri::from_static("https://e-hentai.org/")));
+        assert!(!ehentai.is_listing_match(&Uri::from_static(
+            "https://e-hentai.org/g/1234567/0123456789/"
+        )));
+
+        let listing = ehentai
+            .parse_listing_page(&fixture("ehentai/listing.html"))
+            .unwrap();
+        let galleries = listing
+            .galleries
+            .iter()
+            .map(|gallery| (gallery.url.as_str(), gallery.language.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            galleries,
+            [
+                (
+                    "https://e-hentai.org/g/1234567/0123456789/",
+                    Some("english")
+                ),
+                // from the title
+                (
+                    "https://e-hentai.org/g/1200000/fedcba9876/",
+                    Some("english")
+                ),
+                ("https://e-hentai.org/g/1111111/aaaaaaaaaa/", None),
+            ]
+        );
+        assert_eq!(
+            listing.galleries[2].title.as_deref(),
+            Some("(C101) [Example Circle] Sample Gallery")
+        );
+        assert_eq!(
+            listing.next.unwrap(),
+            "https://e-hentai.org/?f_search=example+circle&next=1111111"
+        );
+    }
+
+    #[test]
+    fn reads_gdata_details() {
+        let gdata = serde_json::from_slice::<Gdata>(&fixture("ehentai/gdata.json")).unwrap();
+        let mut gmetadata = gdata.gmetadata.into_iter();
+
+        let details = GalleryDetails::from(gmetadata.next().unwrap());
+        assert_eq!(
+            details.title_original.as_deref(),
+            Some("[サンプル] サンプル [英訳]")
+        );
+        assert_eq!(details.category.as_deref(), Some("Doujinshi"));
+        assert_eq!(details.uploader.as_deref(), Some("uploader"));
+        assert_eq!(details.posted, Some(1682942400));
+        assert_eq!(details.pages, Some(45));
+        assert_eq!(details.size, Some(52428800));
+        assert_eq!(details.rating, Some(4.52));
+        assert_eq!(details.tags[2], "artist:example artist");
+
+        let refused = gmetadata.next().unwrap();
+        assert_eq!(refused.gid, 7654321);
+        assert!(refused.error.is_some());
+    }
+
+    #[test]
+    fn rejects_malformed_gallery_pages() {
+        let ehentai = Ehentai::new();
+
+        assert!(ehentai
+            .parse_listing(true, &fixture("malformed/ehentai_no_title.html"))
+            .is_err());
+        // later pages don't need the title
+        assert!(ehentai
+            .parse_listing(false, &fixture("malformed/ehentai_no_title.html"))
+            .is_ok());
+        assert!(ehentai
+            .parse_listing(false, &fixture("malformed/ehentai_bad_link.html"))
+            .is_err());
+    }
+}
@@ -3,12 +3,15 @@ use regex::Regex;
 
 use hyper::Uri;
 
-use crate::{downloaders::Downloader, request::request};
+use crate::client::ListedGallery;
+use crate::downloaders::{Downloader, ListingPage};
 
 use crate::downloaders::utils;
+use crate::error::SelectorNotFound;
+use crate::transport::Ctx;
 
 use super::utils::common_url_pattern_donwloader::{CommonUrlPatternDownloader, GalleryInfo};
-use super::utils::{CollectResponse, GetHtmlTag, TagWithParser};
+use super::utils::{GetHtmlTag, TagWithParser};
 
 pub struct Imhentai {
     name: &'static str,
@@ -23,6 +26,14 @@ pub struct Imhentai {
     img_url_attr: &'static str,
     first_image_selector: &'static [&'static str],
     full_image_selector: &'static [&'static str],
+
+    listing_re: Regex,
+    listing_selector: &'static str,
+    listing_gallery_selector: &'static str,
+    listing_link_selector: &'static [&'static str],
+    listing_language_selector: &'static str,
+    listing_language_re: Regex,
+    listing_next_selector: &'static [&'static str],
 }
 
 impl Imhentai {
@@ -40,16 +51,70 @@ impl Imhentai {
             img_url_attr: "data-src",
             first_image_selector: &["div#append_thumbs", "div.gthumb", "a"],
             full_image_selector: &["div.gview", "img#gimg"],
+
+            listing_re: Regex::new(
+                r"^/(?:(?:tag|artist|group|parody|character|language|category)/[^/]+|search)/?$",
+            )
+            .unwrap(),
+            listing_selector: "div.galleries",
+            listing_gallery_selector: "div.thumb",
+            listing_link_selector: &["h2.gallery_title", "a"],
+            listing_language_selector: "a.thumb_flag",
+            listing_language_re: Regex::new(r"^/language/(?P<language>[^/]+)/?$").unwrap(),
+            listing_next_selector: &["ul.pagination", "a.next"],
         }
     }
 
+    fn parse_listing_page(&self, page_url: &Uri, page: &[u8]) -> Result<ListingPage> {
+        let page = String::from_utf8_lossy(page);
+        let dom = tl::parse(&page, Default::default())?;
+        let html = &dom.get_html_tag()?;
+
+        let listing = utils::first_tag(html.tag, html.parser, self.listing_selector)
+            .ok_or_else(|| SelectorNotFound(self.listing_selector.to_owned()))?;
+        let galleries = utils::all_tags(listing, html.parser, self.listing_gallery_selector)
+            .into_iter()
+            .map(|gallery| {
+                let gallery = TagWithParser {
+                    tag: gallery,
+                    parser: html.parser,
+                };
+                let link = gallery.query_selector_mutliple(self.listing_link_selector.iter())?;
+                let href = utils::attr(link.tag, "href").context("empty 'href' attribute")?;
+                let url = utils::merge_uris(&Uri::try_from(href)?, page_url)?;
+                let language =
+                    utils::first_tag(gallery.tag, html.parser, self.listing_language_selector)
+                        .and_then(|flag| utils::attr(flag, "href"))
+                        .and_then(|href| {
+                            let captures = self.listing_language_re.captures(&href)?;
+                            Some(captures["language"].to_lowercase())
+                        });
+
+                Ok(ListedGallery {
+                    url: url.to_string(),
+                    title: Some(link.tag.inner_text(html.parser).trim().to_owned()),
+                    language,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // the last page has no link to the next one
+        let next = html
+            .query_selector_mutliple(self.listing_next_selector.iter())
+            .ok()
+            .and_then(|next| utils::attr(next.tag, "href"))
+            .map(|href| utils::merge_uris(&Uri::try_from(href)?, page_url))
+            .transpose()?;
+
+        Ok(ListingPage { galleries, next })
+    }
+
     #[inline]
     fn is_gallery_path_match(&self, uri: &Uri) -> bool {
         self.path_re.is_match(uri.path())
     }
 }
 
-#[async_trait::async_trait]
 impl CommonUrlPatternDownloader for Imhentai {
     fn get_info(&self, html: &TagWithParser<'_, '_>) -> Result<GalleryInfo> {
         let info = html
@@ -57,14 +122,16 @@ impl CommonUrlPatternDownloader for Imhentai {
             .and_then(|mut q| q.next())
             .and_then(|node| node.get(html.parser))
             .and_then(|node| node.as_tag())
-            .with_context(|| format!("selector not found: {}", self.info_selector))?;
+            .ok_or_else(|| SelectorNotFound(self.info_selector.to_owned()))?;
 
         let title = info
             .query_selector(html.parser, self.title_selector)
             .and_then(|mut q| q.next())
             .and_then(|node| node.get(html.parser))
             .and_then(|node| node.as_tag())
-            .with_context(|| format!("selector not found: {}", self.title_selector))?
+            .ok_or_else(|| {
+                SelectorNotFound(format!("{} > {}", self.info_selector, self.title_selector))
+            })?
             .inner_text(html.parser)
             .to_string();
 
@@ -73,7 +140,9 @@ impl CommonUrlPatternDownloader for Imhentai {
             .and_then(|mut q| q.next())
             .and_then(|node| node.get(html.parser))
             .and_then(|node| node.as_tag())
-            .with_context(|| format!("selctor not found: {}", self.pages_selector))?
+            .ok_or_else(|| {
+                SelectorNotFound(format!("{} > {}", self.info_selector, self.pages_selector))
+            })?
             .inner_text(html.parser);
 
         let pages_count = self
@@ -99,10 +168,8 @@ impl CommonUrlPatternDownloader for Imhentai {
         Uri::try_from(url.as_bytes()).context("failed to create uri")
     }
 
-    async fn get_image_pattern_from_first_image_page(&self, first_image_url: &Uri) -> Result<Uri> {
-        let page = request(first_image_url).await?.collect_response().await?;
-
-        let page = String::from_utf8_lossy(page.as_slice());
+    fn parse_first_image_page(&self, page: &[u8]) -> Result<Uri> {
+        let page = String::from_utf8_lossy(page);
         let dom = tl::parse(&page, Default::default()).context("failed to parse html")?;
         let html = &dom.get_html_tag()?;
 
@@ -116,10 +183,11 @@ impl CommonUrlPatternDownloader for Imhentai {
             .with_context(|| format!("no {attr:?} attribute"))?
             .with_context(|| format!("empty {attr:?} attribute"))?;
 
-        Ok(Uri::try_from(img.as_bytes()).context("failed to create uri")?)
+        Uri::try_from(img.as_bytes()).context("failed to create uri")
     }
 }
 
+#[async_trait::async_trait]
 impl Downloader for Imhentai {
     fn is_gallery_match(&self, gallery: &Uri) -> bool {
         utils::is_supported_scheme(gallery)
@@ -127,7 +195,113 @@ impl Downloader for Imhentai {
             && self.is_gallery_path_match(gallery)
     }
 
+    fn gallery_id(&self, gallery: &Uri) -> Option<String> {
+        utils::capture_gallery_id(&self.path_re, gallery)
+    }
+
     fn name(&self) -> &'static str {
         self.name
     }
+
+    fn is_listing_match(&self, url: &Uri) -> bool {
+        utils::is_supported_scheme(url)
+            && utils::is_proper_authority(url, self.authority)
+            && self.listing_re.is_match(url.path())
+    }
+
+    async fn listing_page(&self, ctx: &Ctx, url: &Uri) -> Result<ListingPage> {
+        let page = ctx.page(url).await?;
+        ctx.parse(url, &page, |page| self.parse_listing_page(url, page))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloaders::fixture;
+
+    fn gallery() -> Uri {
+        Uri::from_static("https://imhentai.xxx/gallery/1234567/")
+    }
+
+    #[test]
+    fn matches_gallery_urls() {
+        let imhentai = Imhentai::new();
+
+        assert!(imhentai.is_gallery_match(&gallery()));
+        assert!(
+            !imhentai.is_gallery_match(&Uri::from_static("https://imhentai.xxx/view/1234567/1/"))
+        );
+        assert!(!imhentai.is_gallery_match(&Uri::from_static("ftp://imhentai.xxx/gallery/1/")));
+        assert_eq!(imhentai.gallery_id(&gallery()).as_deref(), Some("1234567"));
+    }
+
+    #[test]
+    fn parses_gallery_page() {
+        let (title, pages, first_image) = Imhentai::new()
+            .parse_gallery_page(&gallery(), &fixture("imhentai/gallery.html"))
+            .unwrap();
+
+        assert_eq!(title, "Sample Gallery");
+        assert_eq!(pages, 18);
+        assert_eq!(first_image, "https://imhentai.xxx/view/1234567/1/");
+    }
+
+    #[test]
+    fn parses_first_image_page() {
+        let url = Imhentai::new()
+            .parse_first_image_page(&fixture("imhentai/image.html"))
+            .unwrap();
+
+        assert_eq!(url, "https://m7.imhentai.xxx/021/abcdef0123/1.webp");
+    }
+
+    #[test]
+    fn parses_listing_page() {
+        let imhentai = Imhentai::new();
+        let artist = Uri::from_static("https://imhentai.xxx/artist/example-artist/");
+
+        assert!(imhentai.is_listing_match(&artist));
+        assert!(imhentai.is_listing_match(&Uri::from_static(
+            "https://imhentai.xxx/search/?key=example"
+        )));
+        assert!(!imhentai.is_listing_match(&gallery()));
+
+        let listing = imhentai
+            .parse_listing_page(&artist, &fixture("imhentai/listing.html"))
+            .unwrap();
+        let galleries = listing
+            .galleries
+            .iter()
+            .map(|gallery| (gallery.url.as_str(), gallery.language.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            galleries,
+            [
+                ("https://imhentai.xxx/gallery/1234569/", Some("english")),
+                ("https://imhentai.xxx/gallery/1234568/", Some("japanese")),
+                ("https://imhentai.xxx/gallery/1234567/", Some("english")),
+            ]
+        );
+        assert_eq!(
+            listing.galleries[2].title.as_deref(),
+            Some("Sample Gallery")
+        );
+        assert_eq!(
+            listing.next.unwrap(),
+            "https://imhentai.xxx/artist/example-artist/?page=2"
+        );
+    }
+
+    #[test]
+    fn rejects_other_pages() {
+        let imhentai = Imhentai::new();
+
+        assert!(imhentai
+            .parse_gallery_page(&gallery(), &fixture("imhentai/image.html"))
+            .is_err());
+        assert!(imhentai
+            .parse_first_image_page(&fixture("imhentai/gallery.html"))
+            .is_err());
+    }
 }
@@ -7,6 +7,10 @@ use anyhow::Result;
 use hyper::Uri;
 use tokio::sync::mpsc::Sender;
 
+use crate::client::{GalleryDetails, ListedGallery};
+use crate::page_range::PageRange;
+use crate::transport::Ctx;
+
 mod imhentai;
 use imhentai::Imhentai;
 
@@ -16,11 +20,32 @@ use ehentai::Ehentai;
 mod nhentai;
 use nhentai::Nhentai;
 
+mod declarative;
+pub use declarative::SiteDefinition;
+
 mod utils;
+pub(crate) use utils::CollectResponse;
+
+/// image page with its 1-based index in the gallery
+pub struct Page {
+    pub index: usize,
+    pub url: Uri,
+    /// image url found while parsing, spares the first attempt
+    /// a [`Downloader::resolve_image_url`]
+    pub image: Option<Uri>,
+}
+
+/// one page of a search, tag, artist or group listing
+pub(crate) struct ListingPage {
+    pub galleries: Vec<ListedGallery>,
+    pub next: Option<Uri>,
+}
 
 pub enum Msg {
     Title(String),
-    Images(Vec<Uri>),
+    /// pages in the whole gallery, when the site tells before they are all found
+    PageCount(usize),
+    Images(Vec<Page>),
     Error(anyhow::Error),
 }
 
@@ -28,8 +53,10 @@ pub enum Msg {
 pub(crate) trait ParserTask: Sync + Send {
     async fn try_start_parser_task(
         self: Arc<Self>,
+        ctx: Ctx,
         tx: Sender<Msg>,
         gallery: Arc<Uri>,
+        pages: Arc<PageRange>,
     ) -> Result<()>;
 }
 
@@ -37,16 +64,55 @@ pub(crate) trait ParserTask: Sync + Send {
 pub trait Downloader: Sync + Send + ParserTask {
     fn name(&self) -> &'static str;
     fn is_gallery_match(&self, gallery: &Uri) -> bool;
+    /// site specific gallery id, used as the download history key
+    fn gallery_id(&self, gallery: &Uri) -> Option<String>;
 
-    async fn resolve_image_url<'a>(&self, url: &'a Uri) -> Result<Cow<'a, Uri>> {
+    async fn resolve_image_url<'a>(&self, _ctx: &Ctx, url: &'a Uri) -> Result<Cow<'a, Uri>> {
         Ok(Cow::Borrowed(url))
     }
+
+    /// newest revision of `gallery` when the site lists newer ones
+    async fn newer_version(&self, _ctx: &Ctx, _gallery: &Uri) -> Result<Option<Uri>> {
+        Ok(None)
+    }
+
+    /// search, tag, artist or group listing of the site
+    fn is_listing_match(&self, _url: &Uri) -> bool {
+        false
+    }
+
+    /// galleries of one listing page and the url of the next one
+    async fn listing_page(&self, _ctx: &Ctx, url: &Uri) -> Result<ListingPage> {
+        anyhow::bail!("{} has no listing at {url}", self.name())
+    }
+
+    /// most galleries [`Downloader::details`] takes at once, 0 if the site has no api
+    fn details_batch(&self) -> usize {
+        0
+    }
+
+    /// details of `galleries` from the site api, in the same order
+    async fn details(&self, _ctx: &Ctx, _galleries: &[Uri]) -> Result<Vec<GalleryDetails>> {
+        Ok(Vec::new())
+    }
 }
 
 impl dyn Downloader {
-    pub async fn start_parser_task(self: Arc<Self>, tx: Sender<Msg>, gallery: Arc<Uri>) {
-        if let Err(e) = self.try_start_parser_task(tx.clone(), gallery).await {
-            tx.send(Msg::Error(e)).await.expect("failed to send");
+    pub(crate) async fn start_parser_task(
+        self: Arc<Self>,
+        ctx: Ctx,
+        tx: Sender<Msg>,
+        gallery: Arc<Uri>,
+        pages: Arc<PageRange>,
+    ) {
+        if let Err(e) = self
+            .try_start_parser_task(ctx, tx.clone(), gallery, pages)
+            .await
+        {
+            // the receiver is gone only when the gallery download was dropped
+            if tx.send(Msg::Error(e)).await.is_err() {
+                tracing::debug!("parser task error after the download ended");
+            }
         }
     }
 }
@@ -62,3 +128,12 @@ pub fn downloaders() -> &'static [Arc<dyn Downloader>] {
         ])
     })
 }
+
+/// page saved in `tests/fixtures/`
+#[cfg(test)]
+pub(crate) fn fixture(path: &str) -> Vec<u8> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(path);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("failed to read {path:?}: {e}"))
+}
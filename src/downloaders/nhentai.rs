@@ -2,12 +2,21 @@ use anyhow::{Context, Result};
 use hyper::Uri;
 use regex::Regex;
 
-use crate::request::request;
-
 use super::utils::common_url_pattern_donwloader::GalleryInfo;
+use super::utils::GetHtmlTag;
+use super::utils::TagWithParser;
 use super::utils::{self, common_url_pattern_donwloader::CommonUrlPatternDownloader};
-use super::utils::{CollectResponse, TagWithParser};
-use super::Downloader;
+use super::{Downloader, ListingPage};
+use crate::client::ListedGallery;
+use crate::error::SelectorNotFound;
+use crate::transport::Ctx;
+
+/// ids of the language tags in `data-tags` of listed galleries
+const LANGUAGE_TAGS: &[(&str, &str)] = &[
+    ("12227", "english"),
+    ("6346", "japanese"),
+    ("29963", "chinese"),
+];
 
 pub struct Nhentai {
     name: &'static str,
@@ -25,6 +34,13 @@ pub struct Nhentai {
 
     img_section_selector: &'static str,
     img_section_img_selector: &'static str,
+
+    listing_re: Regex,
+    listing_selector: &'static str,
+    listing_gallery_selector: &'static str,
+    listing_link_selector: &'static str,
+    listing_title_selector: &'static str,
+    listing_next_selector: &'static [&'static str],
 }
 
 impl Nhentai {
@@ -45,15 +61,70 @@ impl Nhentai {
 
             img_section_selector: "section#image-container",
             img_section_img_selector: "img[src]",
+
+            listing_re: Regex::new(
+                r"^/(?:(?:tag|artist|group|parody|character|language|category)/[^/]+|search)/?$",
+            )
+            .unwrap(),
+            listing_selector: "div.index-container",
+            listing_gallery_selector: "div.gallery",
+            listing_link_selector: "a.cover",
+            listing_title_selector: "div.caption",
+            listing_next_selector: &["section.pagination", "a.next"],
         }
     }
 
+    fn parse_listing_page(&self, page_url: &Uri, page: &[u8]) -> Result<ListingPage> {
+        let page = String::from_utf8_lossy(page);
+        let dom = tl::parse(&page, Default::default())?;
+        let html = &dom.get_html_tag()?;
+
+        let listing = utils::first_tag(html.tag, html.parser, self.listing_selector)
+            .ok_or_else(|| SelectorNotFound(self.listing_selector.to_owned()))?;
+        let galleries = utils::all_tags(listing, html.parser, self.listing_gallery_selector)
+            .into_iter()
+            .map(|gallery| {
+                let href = utils::first_tag(gallery, html.parser, self.listing_link_selector)
+                    .and_then(|link| utils::attr(link, "href"))
+                    .with_context(|| format!("no link in {}", self.listing_gallery_selector))?;
+                let url = utils::merge_uris(&Uri::try_from(href)?, page_url)?;
+                let title = utils::first_tag(gallery, html.parser, self.listing_title_selector)
+                    .map(|caption| caption.inner_text(html.parser).trim().to_owned());
+                let language = utils::attr(gallery, "data-tags").and_then(|tags| {
+                    tags.split_whitespace().find_map(|id| {
+                        LANGUAGE_TAGS
+                            .iter()
+                            .find(|(tag, _)| *tag == id)
+                            .map(|(_, language)| language.to_string())
+                    })
+                });
+
+                Ok(ListedGallery {
+                    url: url.to_string(),
+                    title,
+                    language,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // the last page has no link to the next one
+        let next = html
+            .query_selector_mutliple(self.listing_next_selector.iter())
+            .ok()
+            .and_then(|next| utils::attr(next.tag, "href"))
+            .map(|href| utils::merge_uris(&Uri::try_from(href)?, page_url))
+            .transpose()?;
+
+        Ok(ListingPage { galleries, next })
+    }
+
     #[inline]
     fn is_gallery_path_match(&self, uri: &Uri) -> bool {
         self.path_re.is_match(uri.path())
     }
 }
 
+#[async_trait::async_trait]
 impl Downloader for Nhentai {
     fn is_gallery_match(&self, gallery: &Uri) -> bool {
         utils::is_supported_scheme(gallery)
@@ -61,12 +132,26 @@ impl Downloader for Nhentai {
             && self.is_gallery_path_match(gallery)
     }
 
+    fn gallery_id(&self, gallery: &Uri) -> Option<String> {
+        utils::capture_gallery_id(&self.path_re, gallery)
+    }
+
     fn name(&self) -> &'static str {
         self.name
     }
+
+    fn is_listing_match(&self, url: &Uri) -> bool {
+        utils::is_supported_scheme(url)
+            && utils::is_proper_authority(url, self.authority)
+            && self.listing_re.is_match(url.path())
+    }
+
+    async fn listing_page(&self, ctx: &Ctx, url: &Uri) -> Result<ListingPage> {
+        let page = ctx.page(url).await?;
+        ctx.parse(url, &page, |page| self.parse_listing_page(url, page))
+    }
 }
 
-#[async_trait::async_trait]
 impl CommonUrlPatternDownloader for Nhentai {
     fn get_info(&self, html: &TagWithParser<'_, '_>) -> Result<GalleryInfo> {
         let info = html
@@ -74,14 +159,16 @@ impl CommonUrlPatternDownloader for Nhentai {
             .and_then(|mut q| q.next())
             .and_then(|node| node.get(html.parser))
             .and_then(|node| node.as_tag())
-            .with_context(|| format!("selector not found: {}", self.info_selector))?;
+            .ok_or_else(|| SelectorNotFound(self.info_selector.to_owned()))?;
 
         let title = info
             .query_selector(html.parser, self.title_selector)
             .and_then(|mut q| q.next())
             .and_then(|node| node.get(html.parser))
             .and_then(|node| node.as_tag())
-            .with_context(|| format!("selector not found: {}", self.title_selector))?
+            .ok_or_else(|| {
+                SelectorNotFound(format!("{} > {}", self.info_selector, self.title_selector))
+            })?
             .inner_text(html.parser)
             .to_string();
 
@@ -96,14 +183,24 @@ impl CommonUrlPatternDownloader for Nhentai {
             })
             .and_then(|node| node.get(html.parser))
             .and_then(|node| node.as_tag())
-            .with_context(|| format!("selctor not found: {}", self.info_selector))?;
+            .ok_or_else(|| {
+                SelectorNotFound(format!(
+                    "{} > {}",
+                    self.info_selector, self.info_field_selector
+                ))
+            })?;
 
         let pages_count = pages_count_tag
             .query_selector(html.parser, self.info_value_selector)
             .and_then(|mut q| q.next())
             .and_then(|node| node.get(html.parser))
             .and_then(|node| node.as_tag())
-            .with_context(|| format!("selctor not found: {}", self.info_value_selector))?
+            .ok_or_else(|| {
+                SelectorNotFound(format!(
+                    "{} > {} > {}",
+                    self.info_selector, self.info_field_selector, self.info_value_selector
+                ))
+            })?
             .inner_text(html.parser)
             .parse()
             .context("failed to parse pages count (usize)")?;
@@ -124,9 +221,8 @@ impl CommonUrlPatternDownloader for Nhentai {
         Ok(Uri::try_from(img_url.as_bytes())?)
     }
 
-    async fn get_image_pattern_from_first_image_page(&self, first_image_url: &Uri) -> Result<Uri> {
-        let page = request(first_image_url).await?.collect_response().await?;
-        let page = String::from_utf8_lossy(&page);
+    fn parse_first_image_page(&self, page: &[u8]) -> Result<Uri> {
+        let page = String::from_utf8_lossy(page);
 
         let dom = tl::parse(&page, Default::default())?;
         let parser = dom.parser();
@@ -136,7 +232,7 @@ impl CommonUrlPatternDownloader for Nhentai {
             .and_then(|mut q| q.next())
             .and_then(|node| node.get(parser))
             .map(|node| node.inner_html(parser))
-            .with_context(|| format!("selctor not found: {}", self.img_section_selector))?;
+            .ok_or_else(|| SelectorNotFound(self.img_section_selector.to_owned()))?;
 
         let section_dom = tl::parse(&section, Default::default())?;
         let parser = section_dom.parser();
@@ -154,3 +250,113 @@ impl CommonUrlPatternDownloader for Nhentai {
         Ok(Uri::try_from(url.as_bytes())?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloaders::fixture;
+
+    fn gallery() -> Uri {
+        Uri::from_static("https://nhentai.net/g/123456/")
+    }
+
+    #[test]
+    fn matches_gallery_urls() {
+        let nhentai = Nhentai::new();
+
+        assert!(nhentai.is_gallery_match(&gallery()));
+        assert!(nhentai.is_gallery_match(&Uri::from_static("http://nhentai.net/g/123456")));
+        assert!(!nhentai.is_gallery_match(&Uri::from_static("https://nhentai.net/g/123456/1/")));
+        assert!(!nhentai.is_gallery_match(&Uri::from_static("https://nhentai.to/g/123456/")));
+        assert_eq!(nhentai.gallery_id(&gallery()).as_deref(), Some("123456"));
+    }
+
+    #[test]
+    fn parses_gallery_page() {
+        let (title, pages, first_image) = Nhentai::new()
+            .parse_gallery_page(&gallery(), &fixture("nhentai/gallery.html"))
+            .unwrap();
+
+        assert_eq!(title, "[Example Circle] Sample Gallery [English]");
+        assert_eq!(pages, 24);
+        assert_eq!(first_image, "https://nhentai.net/g/123456/1/");
+    }
+
+    #[test]
+    fn parses_first_image_page() {
+        let url = Nhentai::new()
+            .parse_first_image_page(&fixture("nhentai/image.html"))
+            .unwrap();
+
+        assert_eq!(url, "https://i3.nhentai.net/galleries/987654/1.jpg");
+    }
+
+    #[test]
+    fn parses_listing_pages() {
+        let nhentai = Nhentai::new();
+        let tag = Uri::from_static("https://nhentai.net/tag/example/");
+
+        assert!(nhentai.is_listing_match(&tag));
+        for url in [
+            "https://nhentai.net/artist/example-artist/",
+            "https://nhentai.net/group/example-circle/?page=3",
+            "https://nhentai.net/search/?q=example",
+        ] {
+            assert!(nhentai.is_listing_match(&Uri::from_static(url)), "{url}");
+        }
+        assert!(!nhentai.is_listing_match(&gallery()));
+
+        let listing = nhentai
+            .parse_listing_page(&tag, &fixture("nhentai/listing.html"))
+            .unwrap();
+        assert_eq!(listing.galleries.len(), 4);
+        assert_eq!(
+            listing.galleries[1],
+            ListedGallery {
+                url: "https://nhentai.net/g/400003/".to_owned(),
+                title: Some("[Example Circle] Third Sample [English]".to_owned()),
+                language: Some("english".to_owned()),
+            }
+        );
+        let languages = listing
+            .galleries
+            .iter()
+            .map(|gallery| gallery.language.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            languages,
+            [
+                Some("japanese"),
+                Some("english"),
+                Some("chinese"),
+                Some("english")
+            ]
+        );
+        assert_eq!(
+            listing.next.unwrap(),
+            "https://nhentai.net/tag/example/?page=2"
+        );
+
+        let last = nhentai
+            .parse_listing_page(&tag, &fixture("nhentai/listing_last.html"))
+            .unwrap();
+        assert_eq!(last.galleries.len(), 2);
+        assert!(last.next.is_none());
+
+        assert!(nhentai
+            .parse_listing_page(&tag, &fixture("nhentai/gallery.html"))
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_other_pages() {
+        let nhentai = Nhentai::new();
+
+        assert!(nhentai
+            .parse_gallery_page(&gallery(), &fixture("nhentai/image.html"))
+            .is_err());
+        assert!(nhentai
+            .parse_first_image_page(&fixture("nhentai/gallery.html"))
+            .is_err());
+    }
+}
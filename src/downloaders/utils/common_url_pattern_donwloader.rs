@@ -1,11 +1,21 @@
-use std::{borrow::Cow, os::unix::ffi::OsStrExt, path::PathBuf, sync::Arc};
+use std::{
+    borrow::Cow,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::{bail, Context, Result};
 
 use hyper::Uri;
 use tokio::sync::mpsc::Sender;
 
-use crate::{downloaders::ParserTask, request::request};
+use crate::{
+    downloaders::{Page, ParserTask},
+    error,
+    page_range::PageRange,
+    transport::Ctx,
+};
 
 use super::{CollectResponse, GetHtmlTag, TagWithParser};
 
@@ -15,23 +25,42 @@ pub(crate) struct DownloadCtx {
 }
 
 impl DownloadCtx {
-    pub(crate) fn get_urls(&self) -> Result<Vec<Uri>> {
+    /// fails if the other pages can't be numbered like `img_url_pattern`
+    fn new(pages_count: usize, img_url_pattern: Uri) -> Result<Self> {
+        let path = Path::new(img_url_pattern.path());
+        if path.parent().is_none() || path.extension().is_none() {
+            bail!("image url without a file extension: {img_url_pattern:?}");
+        }
+
+        Ok(Self {
+            pages_count,
+            img_url_pattern,
+        })
+    }
+
+    /// urls of the selected pages only
+    pub(crate) fn get_urls(&self, pages: &PageRange) -> Result<Vec<Page>> {
         use hyper::http::uri::PathAndQuery;
 
-        let mut urls = Vec::<Uri>::with_capacity(self.pages_count);
+        // not sized by `pages_count`, it comes from the page and can be anything
+        let mut urls = Vec::<Page>::new();
 
         let path = PathBuf::from(self.img_url_pattern.path());
         let prefix = path.parent().context("failed to get parrent")?;
         let ext = path.extension().context("failed to get image extension")?;
 
-        for page in 1..=self.pages_count {
+        for page in (1..=self.pages_count).filter(|&page| pages.contains(page)) {
             let path_and_query = prefix.join(page.to_string()).with_extension(ext);
 
             let path_and_query = path_and_query.as_os_str().as_bytes();
 
             let mut parts = self.img_url_pattern.clone().into_parts();
             parts.path_and_query = Some(PathAndQuery::try_from(path_and_query)?);
-            urls.push(Uri::from_parts(parts)?);
+            urls.push(Page {
+                index: page,
+                url: Uri::from_parts(parts)?,
+                image: None,
+            });
         }
 
         Ok(urls)
@@ -47,40 +76,48 @@ pub(crate) struct GalleryInfo {
 pub(crate) trait CommonUrlPatternDownloader: Sync + Send {
     fn get_first_image_url(&self, html: &TagWithParser<'_, '_>) -> Result<Uri>;
     fn get_info(&self, info_tag: &TagWithParser<'_, '_>) -> Result<GalleryInfo>;
-    async fn get_image_pattern_from_first_image_page(&self, first_image_page: &Uri) -> Result<Uri>;
+    /// url of the full image on the first image page, the others are numbered like it
+    fn parse_first_image_page(&self, first_image_page: &[u8]) -> Result<Uri>;
 
-    async fn parse_ctx(
+    /// returns: (title, pages count, first image page url)
+    fn parse_gallery_page(
         &self,
         gallery_uri: &Uri,
         gallery_page: &[u8],
-    ) -> Result<(String, DownloadCtx)> {
+    ) -> Result<(String, usize, Uri)> {
         let page = String::from_utf8_lossy(gallery_page);
 
-        let (title, pages_count, first_image) = {
-            let dom = tl::parse(&page, Default::default())
-                .with_context(|| format!("failed to parse page for {gallery_uri:?}"))?;
+        let dom = tl::parse(&page, Default::default())
+            .with_context(|| format!("failed to parse page for {gallery_uri:?}"))?;
 
-            let html = &dom.get_html_tag()?;
+        let html = &dom.get_html_tag()?;
 
-            let info = self.get_info(html)?;
+        let info = self.get_info(html)?;
 
-            let first_image = self.get_first_image_url(html)?;
-            let first_image = super::merge_uris(&first_image, gallery_uri);
+        let first_image = self.get_first_image_url(html)?;
+        let first_image = super::merge_uris(&first_image, gallery_uri)?;
 
-            (info.title, info.pages_count, first_image)
-        };
+        Ok((info.title, info.pages_count, first_image))
+    }
 
-        let img_url_pattern = self
-            .get_image_pattern_from_first_image_page(&first_image)
-            .await?;
-
-        Ok((
-            title,
-            DownloadCtx {
-                pages_count,
-                img_url_pattern,
-            },
-        ))
+    async fn parse_ctx(
+        &self,
+        ctx: &Ctx,
+        gallery_uri: &Uri,
+        gallery_page: &[u8],
+    ) -> Result<(String, DownloadCtx)> {
+        let (title, pages_count, first_image) = ctx.parse(gallery_uri, gallery_page, |page| {
+            self.parse_gallery_page(gallery_uri, page)
+        })?;
+
+        let page = ctx.page(&first_image).await?;
+        let download = ctx.parse(&first_image, &page, |page| {
+            let img_url_pattern =
+                super::merge_uris(&self.parse_first_image_page(page)?, &first_image)?;
+            DownloadCtx::new(pages_count, img_url_pattern)
+        })?;
+
+        Ok((title, download))
     }
 }
 
@@ -88,15 +125,19 @@ pub(crate) trait CommonUrlPatternDownloader: Sync + Send {
 impl<T: CommonUrlPatternDownloader> ParserTask for T {
     async fn try_start_parser_task(
         self: Arc<Self>,
+        ctx: Ctx,
         tx: Sender<crate::downloaders::Msg>,
         gallery: Arc<Uri>,
+        pages: Arc<PageRange>,
     ) -> Result<()> {
         let mut gallery = Cow::Borrowed(gallery.as_ref());
         if !gallery.path().ends_with('/') {
             use hyper::http::uri::PathAndQuery;
-            let mut parts = gallery.into_owned().into_parts();
+            let mut parts = gallery.clone().into_owned().into_parts();
 
-            let path_and_query = parts.path_and_query.unwrap();
+            let path_and_query = parts
+                .path_and_query
+                .with_context(|| format!("no path in gallery url: {gallery:?}"))?;
             let path = path_and_query.path();
             let query = path_and_query.query();
             let mut path_and_query = String::with_capacity(path.len() + 1);
@@ -113,21 +154,58 @@ impl<T: CommonUrlPatternDownloader> ParserTask for T {
             gallery = Cow::Owned(Uri::from_parts(parts)?);
         }
 
-        let response = request(&gallery).await?;
+        let response = ctx.get(&gallery).await?;
         let code = response.status();
+        let page = response.collect_response().await?;
         if code != hyper::http::StatusCode::OK {
-            bail!(format!("failed to get {gallery}: status code: {code}"))
+            return Err(error::status_error(&gallery, code, &page));
         }
 
-        let page = response.collect_response().await?;
-
         use crate::downloaders::Msg;
 
-        let (title, ctx) = self.parse_ctx(&gallery, &page).await?;
+        let (title, download) = self.parse_ctx(&ctx, &gallery, &page).await?;
 
         tx.send(Msg::Title(title)).await?;
-        tx.send(Msg::Images(ctx.get_urls()?)).await?;
+        tx.send(Msg::PageCount(download.pages_count)).await?;
+        tx.send(Msg::Images(download.get_urls(&pages)?)).await?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_selected_pages_like_the_first_image() {
+        let ctx = DownloadCtx {
+            pages_count: 5,
+            img_url_pattern: Uri::from_static("https://i3.nhentai.net/galleries/987654/1.jpg"),
+        };
+
+        let pages = ctx.get_urls(&"2-3,5".parse().unwrap()).unwrap();
+
+        let pages = pages
+            .iter()
+            .map(|page| (page.index, page.url.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pages,
+            [
+                (
+                    2,
+                    "https://i3.nhentai.net/galleries/987654/2.jpg".to_owned()
+                ),
+                (
+                    3,
+                    "https://i3.nhentai.net/galleries/987654/3.jpg".to_owned()
+                ),
+                (
+                    5,
+                    "https://i3.nhentai.net/galleries/987654/5.jpg".to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -1,5 +1,13 @@
+use std::collections::VecDeque;
+use std::future::Future;
+
 use anyhow::{Context, Result};
 use tl::{queryselector::QuerySelectorIterator, HTMLTag, Parser, VDom};
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use crate::error::SelectorNotFound;
+use crate::transport::Body;
 
 pub(crate) mod common_url_pattern_donwloader;
 
@@ -16,7 +24,16 @@ pub(crate) fn is_supported_scheme(uri: &hyper::Uri) -> bool {
 }
 
 #[inline]
-pub(crate) fn merge_uris(main: &hyper::Uri, fallback: &hyper::Uri) -> hyper::Uri {
+pub(crate) fn capture_gallery_id(path_re: &regex::Regex, uri: &hyper::Uri) -> Option<String> {
+    path_re
+        .captures(uri.path())
+        .and_then(|captures| captures.name("gallery_id"))
+        .map(|id| id.as_str().to_owned())
+}
+
+/// `main` with the parts it lacks taken from `fallback`, e.g. a relative link and its page
+#[inline]
+pub(crate) fn merge_uris(main: &hyper::Uri, fallback: &hyper::Uri) -> Result<hyper::Uri> {
     use hyper::http::uri::Parts;
 
     let mut parts = Parts::default();
@@ -27,7 +44,106 @@ pub(crate) fn merge_uris(main: &hyper::Uri, fallback: &hyper::Uri) -> hyper::Uri
         .or_else(|| fallback.path_and_query())
         .cloned();
 
-    hyper::Uri::from_parts(parts).expect("failed to merge uris")
+    hyper::Uri::from_parts(parts)
+        .with_context(|| format!("failed to resolve {main:?} against {fallback:?}"))
+}
+
+/// first tag matching `selector` inside `tag`
+pub(crate) fn first_tag<'a, 'b>(
+    tag: &'b HTMLTag<'a>,
+    parser: &'b Parser<'a>,
+    selector: &'b str,
+) -> Option<&'b HTMLTag<'a>> {
+    tag.query_selector(parser, selector)?
+        .next()?
+        .get(parser)?
+        .as_tag()
+}
+
+/// every tag matching `selector` inside `tag`
+pub(crate) fn all_tags<'a, 'b>(
+    tag: &'b HTMLTag<'a>,
+    parser: &'b Parser<'a>,
+    selector: &'b str,
+) -> Vec<&'b HTMLTag<'a>> {
+    tag.query_selector(parser, selector)
+        .into_iter()
+        .flatten()
+        .filter_map(|node| node.get(parser)?.as_tag())
+        .collect()
+}
+
+/// attribute `name` of `tag` with `&amp;` decoded, `None` if missing or empty
+pub(crate) fn attr(tag: &HTMLTag<'_>, name: &str) -> Option<String> {
+    let value = tag.attributes().get(name)??.as_utf8_str();
+    Some(value.replace("&amp;", "&")).filter(|value| !value.is_empty())
+}
+
+/// `[English]` and the like in a gallery title, lowercase
+pub(crate) fn title_language(title: &str) -> Option<String> {
+    const LANGUAGES: &[&str] = &[
+        "english",
+        "japanese",
+        "chinese",
+        "korean",
+        "spanish",
+        "french",
+        "german",
+        "russian",
+        "italian",
+        "portuguese",
+        "thai",
+        "vietnamese",
+        "indonesian",
+        "polish",
+    ];
+
+    title
+        .split('[')
+        .skip(1)
+        .filter_map(|rest| Some(rest.split_once(']')?.0.trim().to_lowercase()))
+        .find(|word| LANGUAGES.contains(&word.as_str()))
+}
+
+/// tasks whose outputs are taken in the order they were started,
+/// they are aborted when dropped
+pub(crate) struct Ordered<T> {
+    limit: usize,
+    tasks: VecDeque<JoinHandle<T>>,
+}
+
+impl<T: Send + 'static> Ordered<T> {
+    /// at most `limit` tasks at the same time, see [`Ordered::is_full`]
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            tasks: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.tasks.len() >= self.limit
+    }
+
+    pub(crate) fn push(&mut self, task: impl Future<Output = T> + Send + 'static) {
+        self.tasks.push_back(tokio::spawn(task.in_current_span()));
+    }
+
+    /// output of the oldest task, `None` when there are no tasks left
+    pub(crate) async fn next(&mut self) -> Option<Result<T>> {
+        let task = self.tasks.front_mut()?;
+        let output = task.await.context("failed to join async task");
+        self.tasks.pop_front();
+        Some(output)
+    }
+}
+
+impl<T> Drop for Ordered<T> {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -36,7 +152,7 @@ pub(crate) trait CollectResponse {
 }
 
 #[async_trait::async_trait]
-impl CollectResponse for hyper::Response<hyper::body::Incoming> {
+impl CollectResponse for hyper::Response<Body> {
     async fn collect_response(mut self) -> Result<Vec<u8>> {
         use http_body_util::BodyExt;
 
@@ -120,18 +236,24 @@ impl<'a> QuerySelectorMutliple<'a> for HTMLTag<'a> {
         S: AsRef<str> + 'b,
         I: Iterator<Item = S>,
     {
+        // the whole chain so far, the last selector alone rarely tells what broke
+        let mut chain = String::new();
         selectors.try_fold(self, |current, selector| -> Result<_> {
             let selector = selector.as_ref();
+            if !chain.is_empty() {
+                chain.push_str(" > ");
+            }
+            chain.push_str(selector);
 
             let mut query = current
                 .query_selector(parser, selector)
-                .with_context(|| format!("failed to query selctor: '{selector}'"))?;
+                .with_context(|| format!("invalid selector: {selector:?} in {chain:?}"))?;
 
             query
                 .next()
                 .and_then(|node| node.get(parser))
                 .and_then(|node| node.as_tag())
-                .with_context(|| format!("failed to get node for selector: {selector:?}"))
+                .ok_or_else(|| SelectorNotFound(chain.clone()).into())
         })
     }
 }
@@ -0,0 +1,1506 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use http_body_util::BodyExt;
+use hyper::Uri;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tracing::{debug, error, info, warn, Instrument};
+
+use crate::client::{
+    Cancel, DownloadOptions, FailedPage, GalleryDetails, Inner, Metadata, ResolvedPage, Summary,
+};
+use crate::config;
+use crate::downloaders::{self, Downloader, Page};
+use crate::error::{self, Error, ErrorKind};
+use crate::event::{EventKind, GalleryId};
+use crate::transport::Ctx;
+
+#[derive(Clone, Copy)]
+pub(crate) enum Mode {
+    Download,
+    /// stop after resolving the image urls
+    Resolve,
+}
+
+/// publishes events of one gallery on the client's bus
+#[derive(Clone)]
+struct Events {
+    client: Arc<Inner>,
+    gallery: GalleryId,
+}
+
+impl Events {
+    #[inline]
+    fn emit(&self, kind: EventKind) {
+        self.client.events.emit(self.gallery, kind);
+    }
+}
+
+/// resolves once the client is cancelled to at least `level`
+pub(crate) async fn wait_cancel(cancel: &mut watch::Receiver<Cancel>, level: Cancel) {
+    if cancel.wait_for(|c| *c >= level).await.is_err() {
+        // the client is gone, nothing can cancel anymore
+        std::future::pending::<()>().await;
+    }
+}
+
+/// fails with [`ErrorKind::Cancelled`] once the client is aborted
+async fn abortable<T>(
+    cancel: &mut watch::Receiver<Cancel>,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::select! {
+        res = fut => res,
+        _ = wait_cancel(cancel, Cancel::Aborting) => Err(ErrorKind::Cancelled.into()),
+    }
+}
+
+/// fills `template` from [`SiteOptions::dir_template`], the values can't add directories
+pub(crate) fn gallery_dir(
+    out_dir: &Path,
+    template: &str,
+    site: &str,
+    id: Option<&str>,
+    title: &str,
+) -> PathBuf {
+    let dir = template
+        .replace("{title}", &title.replace('/', "_"))
+        .replace("{site}", site)
+        .replace("{id}", &id.unwrap_or("").replace('/', "_"));
+
+    dir.split('/')
+        .filter(|name| !name.is_empty())
+        .fold(out_dir.to_owned(), |path, name| {
+            path.join(
+                name.chars()
+                    .take(config::MAX_FILE_NAME_LEN)
+                    .collect::<String>(),
+            )
+        })
+}
+
+/// `resolved` is the image url returned by [`Downloader::resolve_image_url`]
+pub(crate) fn page_file_name(page: &Page, resolved: &Uri) -> Result<PathBuf> {
+    let path = Path::new(resolved.path());
+    let ext = path.extension().context("missing image extension")?;
+
+    // keep the gallery numbering even if only some pages are selected
+    Ok(PathBuf::from(format!("{:04}", page.index)).with_extension(ext))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn run(
+    client: Arc<Inner>,
+    gallery: GalleryId,
+    downloader: Arc<dyn Downloader>,
+    url: Arc<Uri>,
+    redirected_from: Option<Arc<Uri>>,
+    options: Arc<DownloadOptions>,
+    ctx: Ctx,
+    mode: Mode,
+) -> Summary {
+    let mut summary = Summary::default();
+    let events = Events {
+        client: Arc::clone(&client),
+        gallery,
+    };
+
+    info!("gallery started");
+    events.emit(EventKind::GalleryDiscovered {
+        url: url.to_string(),
+        site: downloader.name(),
+    });
+
+    let download = start_download(
+        client,
+        downloader,
+        url,
+        redirected_from,
+        options,
+        ctx,
+        mode,
+        &events,
+        &mut summary,
+    );
+    if let Err(e) = download.await {
+        summary.error = Some(Error::from_anyhow(e));
+    }
+    summary.resolved.sort_by_key(|page| page.page);
+
+    match &summary.error {
+        Some(e) => error!(error = format!("{e:#}"), "gallery failed"),
+        None => info!(
+            pages = summary.pages,
+            failed = summary.failed.len(),
+            "gallery finished"
+        ),
+    }
+    events.emit(EventKind::GalleryFinished {
+        pages: summary.pages,
+        failed: summary.failed.len(),
+        error_kind: summary.error.as_ref().map(|e| e.kind().clone()),
+        error: summary.error.as_ref().map(|e| format!("{e:#}")),
+    });
+
+    summary
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn start_download(
+    client: Arc<Inner>,
+    downloader: Arc<dyn Downloader>,
+    url: Arc<Uri>,
+    redirected_from: Option<Arc<Uri>>,
+    options: Arc<DownloadOptions>,
+    ctx: Ctx,
+    mode: Mode,
+    events: &Events,
+    summary: &mut Summary,
+) -> Result<()> {
+    const BUFF_SZ: usize = 1024;
+
+    let (parser_tx, mut parser_rx) = mpsc::channel::<downloaders::Msg>(BUFF_SZ);
+
+    let mut set = JoinSet::<(Page, Result<Option<ResolvedPage>>)>::new();
+
+    let parser_task = {
+        let downloader = Arc::clone(&downloader);
+        let url = Arc::clone(&url);
+        let pages = Arc::new(options.pages.clone());
+        let ctx = ctx.clone();
+
+        tokio::spawn(
+            async move {
+                downloader
+                    .start_parser_task(ctx, parser_tx, url, pages)
+                    .await;
+            }
+            .in_current_span(),
+        )
+    };
+
+    // asked for while the parser fetches the gallery, needed with the title
+    let mut details = Some({
+        let client = Arc::clone(&client);
+        let downloader = Arc::clone(&downloader);
+        let ctx = ctx.clone();
+        let url = Arc::clone(&url);
+        tokio::spawn(async move { client.details(&downloader, &ctx, &url).await }.in_current_span())
+    });
+    let count_pages = |total: usize| {
+        let pages = (1..=total)
+            .filter(|&page| options.pages.contains(page))
+            .count();
+        debug!(total, pages, "page count known");
+        events.emit(EventKind::PageCount { pages });
+    };
+    let mut counted = false;
+
+    let mut manga_dir = None;
+    let mut cancel = client.cancel.subscribe();
+
+    // the pages in flight are finished even if the gallery fails, or they would be
+    // cut off and leave their partial files behind
+    let parsed = async {
+        'parse: loop {
+            let msg = tokio::select! {
+                msg = parser_rx.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                _ = wait_cancel(&mut cancel, Cancel::Stopping) => {
+                    summary.cancelled = true;
+                    break;
+                }
+            };
+
+            use downloaders::Msg;
+            match msg {
+                Msg::Title(title) => {
+                    let dir = gallery_dir(
+                        &options.out_dir,
+                        ctx.site().template(),
+                        downloader.name(),
+                        downloader.gallery_id(&url).as_deref(),
+                        &title,
+                    );
+                    if let Mode::Download = mode {
+                        fs::create_dir_all(&dir).await?;
+                    }
+
+                    let details = match details.take() {
+                        Some(mut task) => tokio::select! {
+                            details = &mut task => details.unwrap_or_default(),
+                            _ = wait_cancel(&mut cancel, Cancel::Stopping) => {
+                                task.abort();
+                                GalleryDetails::default()
+                            }
+                        },
+                        None => GalleryDetails::default(),
+                    };
+
+                    info!(title, out_dir = ?dir, "found gallery title");
+                    let metadata = Metadata {
+                        site: downloader.name(),
+                        url: url.to_string(),
+                        title,
+                        out_dir: dir.clone(),
+                        redirected_from: redirected_from.as_ref().map(|url| url.to_string()),
+                        details,
+                    };
+                    events.emit(EventKind::Metadata(metadata.clone()));
+                    if let (Some(total), false) = (metadata.details.pages, counted) {
+                        count_pages(total);
+                        counted = true;
+                    }
+                    summary.metadata = Some(metadata);
+                    manga_dir = Some(Arc::from(dir));
+                }
+                // the site api may have told already
+                Msg::PageCount(total) if !counted => {
+                    count_pages(total);
+                    counted = true;
+                }
+                Msg::PageCount(_) => {}
+                Msg::Images(pages) => {
+                    let Some(out_dir) = manga_dir.as_ref() else {
+                        bail!(
+                            "{} found images before the gallery title",
+                            downloader.name()
+                        );
+                    };
+
+                    debug!(count = pages.len(), "pages queued");
+                    for page in &pages {
+                        events.emit(EventKind::PageQueued {
+                            page: page.index,
+                            url: page.url.to_string(),
+                        });
+                    }
+
+                    for page in pages {
+                        let permit = tokio::select! {
+                            permit = Arc::clone(&client.jobs).acquire_owned() => permit?,
+                            _ = wait_cancel(&mut cancel, Cancel::Stopping) => {
+                                summary.cancelled = true;
+                                break 'parse;
+                            }
+                        };
+
+                        let client = Arc::clone(&client);
+                        let options = Arc::clone(&options);
+                        let events = events.clone();
+                        let downloader = Arc::clone(&downloader);
+                        let out_dir = Arc::clone(out_dir);
+                        let mut cancel = cancel.clone();
+                        let ctx = ctx.clone();
+
+                        let span = tracing::info_span!("page", page = page.index);
+                        set.spawn(
+                            async move {
+                                let mut page = page;
+                                let mut last_error = None;
+                                let attempts = ctx.site().attempts();
+                                let mut made = 0;
+                                for attempt in 1..=attempts {
+                                    made = attempt;
+                                    let res = match mode {
+                                        Mode::Download => download_image(
+                                            &client,
+                                            &ctx,
+                                            &downloader,
+                                            &options,
+                                            &events,
+                                            &mut cancel,
+                                            &out_dir,
+                                            &page,
+                                        )
+                                        .await
+                                        .map(|()| None),
+                                        Mode::Resolve => resolve_image(
+                                            &ctx,
+                                            &downloader,
+                                            &events,
+                                            &mut cancel,
+                                            &out_dir,
+                                            &page,
+                                        )
+                                        .await
+                                        .map(Some),
+                                    }
+                                    .with_context(|| {
+                                        format!(
+                                            "failed to download {:?} (page {})",
+                                            page.url, page.index
+                                        )
+                                    });
+
+                                    match res {
+                                        Ok(resolved) => {
+                                            drop(permit);
+                                            return (page, Ok(resolved));
+                                        }
+                                        // a cancelled client doesn't start new attempts
+                                        Err(e) if *cancel.borrow() >= Cancel::Stopping => {
+                                            last_error = Some(e.context(ErrorKind::Cancelled));
+                                            break;
+                                        }
+                                        Err(e) => {
+                                            // the url found by the parser may be what failed
+                                            page.image = None;
+                                            let kind = ErrorKind::of(&e);
+                                            if attempt == attempts || !kind.is_retryable() {
+                                                last_error = Some(e);
+                                                break;
+                                            }
+
+                                            warn!(
+                                                attempt,
+                                                kind = kind.name(),
+                                                error = format!("{e:#}"),
+                                                "retrying page"
+                                            );
+                                            events.emit(EventKind::PageRetry {
+                                                page: page.index,
+                                                attempt,
+                                                kind: kind.clone(),
+                                                error: format!("{e:#}"),
+                                            });
+                                            if kind == ErrorKind::RateLimited {
+                                                // give the site time before asking again
+                                                let delay = Duration::from_secs(1 << attempt.min(6));
+                                                let cancelled = tokio::select! {
+                                                    _ = tokio::time::sleep(delay) => false,
+                                                    _ = wait_cancel(&mut cancel, Cancel::Stopping) => true,
+                                                };
+                                                if cancelled {
+                                                    last_error = Some(e.context(ErrorKind::Cancelled));
+                                                    break;
+                                                }
+                                            }
+                                            last_error = Some(e);
+                                        }
+                                    }
+                                }
+                                let e =
+                                    last_error.unwrap_or_else(|| anyhow::anyhow!("no attempts made"));
+                                let e = e.context(format!("download failed after {made} attempts"));
+
+                                // reported right away, not once the whole gallery is parsed
+                                error!(url = %page.url, error = format!("{e:#}"), "page failed");
+                                events.emit(EventKind::PageFailed {
+                                    page: page.index,
+                                    kind: ErrorKind::of(&e),
+                                    error: format!("{e:#}"),
+                                });
+                                drop(permit);
+
+                                (page, Err(e))
+                            }
+                            .instrument(span),
+                        );
+
+                        summary.pages += 1;
+                    }
+                }
+                Msg::Error(e) => {
+                    Err(e).context("error happend in downloader task")?;
+                }
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Some(task) = details {
+        task.abort();
+    }
+
+    while let Some(res) = set.join_next().await {
+        match res.context("failed to join async task")? {
+            (_, Ok(Some(resolved))) => summary.resolved.push(resolved),
+            (_, Ok(None)) => {}
+            (page, Err(error)) => {
+                summary.failed.push(FailedPage {
+                    page: page.index,
+                    url: page.url.to_string(),
+                    error: Error::from_anyhow(error),
+                });
+            }
+        }
+    }
+    summary.failed.sort_by_key(|page| page.page);
+
+    if summary.cancelled || parsed.is_err() {
+        parser_task.abort();
+    }
+    parsed?;
+    match parser_task.await {
+        Err(e) if e.is_cancelled() => {}
+        res => res?,
+    }
+
+    Ok(())
+}
+
+async fn resolve_image_url(
+    ctx: &Ctx,
+    downloader: &Arc<dyn Downloader>,
+    events: &Events,
+    cancel: &mut watch::Receiver<Cancel>,
+    page: &Page,
+) -> Result<Uri> {
+    events.emit(EventKind::PageResolving { page: page.index });
+    if let Some(image) = &page.image {
+        return Ok(image.clone());
+    }
+
+    let resolve = async {
+        Ok(timeout(
+            ctx.site().read_timeout(),
+            downloader.resolve_image_url(ctx, &page.url),
+        )
+        .await
+        .context("timed out resolving image url")
+        .context(ErrorKind::Network)??
+        .into_owned())
+    };
+    abortable(cancel, resolve)
+        .await
+        .inspect(|url| debug!(%url, "resolved image url"))
+}
+
+async fn resolve_image(
+    ctx: &Ctx,
+    downloader: &Arc<dyn Downloader>,
+    events: &Events,
+    cancel: &mut watch::Receiver<Cancel>,
+    out_dir: &Path,
+    page: &Page,
+) -> Result<ResolvedPage> {
+    let url = resolve_image_url(ctx, downloader, events, cancel, page).await?;
+    let out = out_dir.join(page_file_name(page, &url)?);
+
+    events.emit(EventKind::PageDone { page: page.index });
+
+    Ok(ResolvedPage {
+        page: page.index,
+        url: url.to_string(),
+        out,
+    })
+}
+
+fn content_length<B>(response: &hyper::Response<B>) -> Option<u64> {
+    response
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download_image(
+    client: &Inner,
+    ctx: &Ctx,
+    downloader: &Arc<dyn Downloader>,
+    options: &DownloadOptions,
+    events: &Events,
+    cancel: &mut watch::Receiver<Cancel>,
+    out_dir: &Path,
+    page: &Page,
+) -> Result<()> {
+    let index = page.index;
+
+    let url = &resolve_image_url(ctx, downloader, events, cancel, page).await?;
+    let read_timeout = ctx.site().read_timeout();
+
+    let file_name = page_file_name(page, url)?;
+    let file_path = out_dir.join(&file_name);
+
+    events.emit(EventKind::PageStarted {
+        page: index,
+        file_name: file_name.clone(),
+    });
+
+    if !options.overwrite && fs::try_exists(&file_path).await? {
+        debug!(path = ?file_path, "image already downloaded");
+        events.emit(EventKind::PageDone { page: index });
+        return Ok(());
+    }
+
+    let mut response = abortable(cancel, async {
+        timeout(read_timeout, ctx.get(url))
+            .await
+            .context("timed out waiting for response")
+            .context(ErrorKind::Network)?
+    })
+    .await?;
+    let code = response.status();
+    if !code.is_success() {
+        return Err(error::status_error(url, code, &[]));
+    }
+    let size = content_length(&response);
+    debug!(size, "downloading image");
+    if let Some(bytes) = size {
+        events.emit(EventKind::PageSize { page: index, bytes });
+    }
+
+    // the final name only appears once the image is complete, so interrupted
+    // downloads are picked up again on the next run
+    let part_path = {
+        let mut name = file_name.into_os_string();
+        name.push(".part");
+        out_dir.join(name)
+    };
+    let mut file = fs::File::create(&part_path)
+        .await
+        .with_context(|| format!("failed to create file: {part_path:?}"))?;
+
+    let write = async {
+        while let Some(next) = timeout(read_timeout, response.frame())
+            .await
+            .context("timed out reading response body")
+            .context(ErrorKind::Network)?
+        {
+            if let Some(chunck) = next?.data_ref() {
+                client.limiter.acquire(chunck.len()).await;
+                ctx.acquire(chunck.len()).await;
+                file.write_all(chunck).await?;
+                events.emit(EventKind::BytesReceived {
+                    page: index,
+                    len: chunck.len(),
+                });
+            }
+        }
+        file.flush().await?;
+        Ok(())
+    };
+    let res = abortable(cancel, write).await;
+    drop(file);
+    if res.is_err() {
+        // the next attempt starts over anyway
+        let _ = fs::remove_file(&part_path).await;
+    }
+    res?;
+
+    fs::rename(&part_path, &file_path)
+        .await
+        .with_context(|| format!("failed to rename {part_path:?} to {file_path:?}"))?;
+
+    info!(path = ?file_path, "page done");
+    events.emit(EventKind::PageDone { page: index });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+
+    use crate::client::{Client, ClientBuilder, Download, ListingOptions};
+    use crate::downloaders::fixture;
+    use crate::error::{ErrorKind, Interstitial};
+    use crate::event::Event;
+    use crate::site_options::SiteOptions;
+    use crate::transport::MockTransport;
+
+    use super::*;
+
+    const GALLERY: &str = "https://nhentai.net/g/123456/";
+    const FIRST_IMAGE_PAGE: &str = "https://nhentai.net/g/123456/1/";
+
+    const EHENTAI: &str = "https://e-hentai.org/g/1234567/0123456789/";
+    const EHENTAI_IMAGE: &str = "https://abcdefg.hijklmnop.hath.network/h/0123456789abcdef/keystamp=1700000000-abcdef;fileindex=123456;xres=1280/001.jpg";
+
+    fn image(page: usize) -> String {
+        format!("https://i3.nhentai.net/galleries/987654/{page}.jpg")
+    }
+
+    /// the nhentai fixtures with images for `pages`
+    fn nhentai(pages: impl IntoIterator<Item = usize>) -> MockTransport {
+        pages.into_iter().fold(
+            MockTransport::new()
+                .page(GALLERY, fixture("nhentai/gallery.html"))
+                .page(FIRST_IMAGE_PAGE, fixture("nhentai/image.html")),
+            |mock, page| mock.page(&image(page), format!("image {page}")),
+        )
+    }
+
+    /// the first and last e-hentai listing pages, image pages 2 and 44 and their image
+    fn ehentai() -> MockTransport {
+        MockTransport::new()
+            .page(EHENTAI, fixture("ehentai/gallery.html"))
+            .page(
+                &format!("{EHENTAI}?p=2"),
+                fixture("ehentai/gallery_last.html"),
+            )
+            .page(
+                "https://e-hentai.org/s/0000003dde/1234567-2",
+                fixture("ehentai/image.html"),
+            )
+            .page(
+                "https://e-hentai.org/s/0000055114/1234567-44",
+                fixture("ehentai/image.html"),
+            )
+    }
+
+    /// removed before and after the test
+    struct OutDir(PathBuf);
+
+    impl OutDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("hdl-{}-{name}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for OutDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn download(
+        mock: Arc<MockTransport>,
+        site: SiteOptions,
+        options: DownloadOptions,
+        mode: Mode,
+    ) -> (Vec<Event>, Summary) {
+        let builder = Client::builder().defaults(site).transport(mock);
+        download_with(builder, GALLERY, options, mode).await
+    }
+
+    async fn download_with(
+        builder: ClientBuilder,
+        gallery: &str,
+        options: DownloadOptions,
+        mode: Mode,
+    ) -> (Vec<Event>, Summary) {
+        let client = builder.jobs(2).build();
+        let gallery = client.gallery(gallery).unwrap();
+        let mut download = match mode {
+            Mode::Download => gallery.download(options),
+            Mode::Resolve => gallery.resolve(options),
+        };
+
+        let mut events = Vec::new();
+        while let Some(event) = download.next_event().await {
+            events.push(event);
+        }
+        (events, download.finish().await)
+    }
+
+    /// position of the first event matching `f`
+    fn position(events: &[Event], f: impl Fn(&EventKind) -> bool) -> usize {
+        events
+            .iter()
+            .position(|event| f(&event.kind))
+            .expect("event not found")
+    }
+
+    #[tokio::test]
+    async fn downloads_selected_pages_in_order() {
+        let out = OutDir::new("selected");
+        let mock = Arc::new(nhentai(1..=24));
+        let options = DownloadOptions::new(&out.0).pages("2-4".parse().unwrap());
+
+        let (events, summary) = download(
+            Arc::clone(&mock),
+            SiteOptions::new(),
+            options,
+            Mode::Download,
+        )
+        .await;
+
+        assert!(summary.error.is_none());
+        assert!(summary.failed.is_empty());
+        assert_eq!(summary.pages, 3);
+        let dir = out.0.join("[Example Circle] Sample Gallery [English]");
+        for page in 2..=4 {
+            let file = dir.join(format!("{page:04}.jpg"));
+            assert_eq!(
+                std::fs::read_to_string(file).unwrap(),
+                format!("image {page}")
+            );
+        }
+        assert!(!dir.join("0001.jpg").exists());
+
+        let requests = mock.requests();
+        assert_eq!(requests[..2], [GALLERY, FIRST_IMAGE_PAGE]);
+        let mut images = requests[2..].to_vec();
+        images.sort();
+        assert_eq!(images, [image(2), image(3), image(4)]);
+
+        assert!(matches!(
+            events[0].kind,
+            EventKind::GalleryDiscovered { .. }
+        ));
+        assert!(matches!(events[1].kind, EventKind::Metadata(_)));
+        for page in 2..=4 {
+            let queued = position(
+                &events,
+                |kind| matches!(kind, EventKind::PageQueued { page: p, .. } if *p == page),
+            );
+            let started = position(
+                &events,
+                |kind| matches!(kind, EventKind::PageStarted { page: p, .. } if *p == page),
+            );
+            let done = position(
+                &events,
+                |kind| matches!(kind, EventKind::PageDone { page: p } if *p == page),
+            );
+            assert!(queued < started && started < done);
+        }
+        assert!(matches!(
+            events.last().unwrap().kind,
+            EventKind::GalleryFinished {
+                pages: 3,
+                failed: 0,
+                error_kind: None,
+                error: None
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn retries_pages() {
+        let out = OutDir::new("retry");
+        let mock = Arc::new(
+            nhentai([1, 3])
+                .error(&image(2), "connection reset")
+                .page(&image(2), "image 2"),
+        );
+        let options = DownloadOptions::new(&out.0).pages("1-3".parse().unwrap());
+
+        let (events, summary) = download(
+            Arc::clone(&mock),
+            SiteOptions::new(),
+            options,
+            Mode::Download,
+        )
+        .await;
+
+        assert!(summary.failed.is_empty());
+        let retry = position(&events, |kind| {
+            matches!(
+                kind,
+                EventKind::PageRetry {
+                    page: 2,
+                    attempt: 1,
+                    ..
+                }
+            )
+        });
+        let done = position(&events, |kind| {
+            matches!(kind, EventKind::PageDone { page: 2 })
+        });
+        assert!(retry < done);
+        assert_eq!(
+            mock.requests()
+                .iter()
+                .filter(|url| **url == image(2))
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_pages_that_keep_failing() {
+        let out = OutDir::new("failing");
+        let mock =
+            Arc::new(nhentai([1, 2, 4]).response(&image(3), StatusCode::SERVICE_UNAVAILABLE, ""));
+        let options = DownloadOptions::new(&out.0).pages("1-4".parse().unwrap());
+
+        let (events, summary) = download(
+            Arc::clone(&mock),
+            SiteOptions::new().retries(2),
+            options,
+            Mode::Download,
+        )
+        .await;
+
+        assert!(summary.error.is_none());
+        assert_eq!(summary.pages, 4);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].page, 3);
+        assert_eq!(summary.failed[0].url, image(3));
+        assert_eq!(
+            summary.failed[0].error.kind().clone(),
+            ErrorKind::Http(StatusCode::SERVICE_UNAVAILABLE)
+        );
+        assert_eq!(
+            mock.requests()
+                .iter()
+                .filter(|url| **url == image(3))
+                .count(),
+            2
+        );
+
+        position(&events, |kind| {
+            matches!(kind, EventKind::PageFailed { page: 3, .. })
+        });
+        assert!(matches!(
+            events.last().unwrap().kind,
+            EventKind::GalleryFinished {
+                pages: 4,
+                failed: 1,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_missing_pages_at_once() {
+        let out = OutDir::new("missing");
+        let mock = Arc::new(nhentai([1, 2]).response(&image(3), StatusCode::NOT_FOUND, ""));
+        let options = DownloadOptions::new(&out.0).pages("1-3".parse().unwrap());
+
+        let (events, summary) = download(
+            Arc::clone(&mock),
+            SiteOptions::new(),
+            options,
+            Mode::Download,
+        )
+        .await;
+
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].error.kind(), &ErrorKind::NotFound);
+        assert_eq!(
+            mock.requests()
+                .iter()
+                .filter(|url| **url == image(3))
+                .count(),
+            1
+        );
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event.kind, EventKind::PageRetry { .. })));
+        position(&events, |kind| {
+            matches!(
+                kind,
+                EventKind::PageFailed {
+                    page: 3,
+                    kind: ErrorKind::NotFound,
+                    ..
+                }
+            )
+        });
+    }
+
+    #[tokio::test]
+    async fn reports_failed_pages_while_others_download() {
+        let out = OutDir::new("failed-early");
+        let mock = Arc::new(
+            nhentai([2, 3])
+                .response(&image(1), StatusCode::NOT_FOUND, "")
+                .delay(&image(2), Duration::from_millis(300))
+                .delay(&image(3), Duration::from_millis(300)),
+        );
+        let options = DownloadOptions::new(&out.0).pages("1-3".parse().unwrap());
+
+        let (events, summary) = download(mock, SiteOptions::new(), options, Mode::Download).await;
+
+        assert_eq!(summary.failed.len(), 1);
+        let failed = position(&events, |kind| {
+            matches!(kind, EventKind::PageFailed { page: 1, .. })
+        });
+        // page 3 only starts once page 1 gave up its job
+        let started = position(&events, |kind| {
+            matches!(kind, EventKind::PageStarted { page: 3, .. })
+        });
+        let done = position(&events, |kind| {
+            matches!(kind, EventKind::PageDone { page: 2 })
+        });
+        assert!(failed < started && failed < done, "{events:#?}");
+        assert!(matches!(
+            events.last().unwrap().kind,
+            EventKind::GalleryFinished { failed: 1, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn limits_the_rate_of_a_site() {
+        let out = OutDir::new("site-rate");
+        let mock = Arc::new(nhentai([1, 2]));
+        // "image 1" and "image 2", one second worth of data each
+        let builder = Client::builder()
+            .transport(mock)
+            .site("nhentai", SiteOptions::new().rate_limit(7));
+        let options = DownloadOptions::new(&out.0).pages("1-2".parse().unwrap());
+
+        let start = tokio::time::Instant::now();
+        let (_, summary) = download_with(builder, GALLERY, options, Mode::Download).await;
+
+        assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(900), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(3), "{elapsed:?}");
+    }
+
+    /// the gallery dir of the nhentai and e-hentai fixtures
+    fn gallery_dir(out: &OutDir) -> PathBuf {
+        out.0.join("[Example Circle] Sample Gallery [English]")
+    }
+
+    /// files of `dir`, sorted
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    /// skips the events of `download` up to the `count`th matching `f`
+    async fn wait_for(download: &mut Download, count: usize, f: impl Fn(&EventKind) -> bool) {
+        let mut seen = 0;
+        while seen < count {
+            let event = download.next_event().await.expect("event not found");
+            if f(&event.kind) {
+                seen += 1;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn finishes_pages_in_flight_when_stopping() {
+        let out = OutDir::new("stop");
+        let delay = Duration::from_millis(300);
+        let mock = Arc::new(
+            nhentai(1..=3)
+                .delay(&image(1), delay)
+                .delay(&image(2), delay),
+        );
+        let client = Client::builder()
+            .transport(Arc::clone(&mock) as _)
+            .jobs(2)
+            .build();
+        let options = DownloadOptions::new(&out.0).pages("1-3".parse().unwrap());
+        let mut download = client.gallery(GALLERY).unwrap().download(options);
+
+        // both jobs are taken, page 3 waits for one of them
+        wait_for(&mut download, 2, |kind| {
+            matches!(kind, EventKind::PageStarted { .. })
+        })
+        .await;
+        client.cancel();
+        let summary = download.finish().await;
+
+        assert!(summary.cancelled);
+        assert!(summary.error.is_none(), "{:?}", summary.error);
+        assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+        assert_eq!(summary.pages, 2);
+        assert_eq!(files(&gallery_dir(&out)), ["0001.jpg", "0002.jpg"]);
+        assert!(!mock.requests().contains(&image(3)));
+    }
+
+    #[tokio::test]
+    async fn leaves_no_partial_files_when_aborted() {
+        let out = OutDir::new("abort");
+        let mock = Arc::new(nhentai([]).stall(&image(1), "image"));
+        let client = Client::builder().transport(mock).build();
+        let options = DownloadOptions::new(&out.0).pages("1".parse().unwrap());
+        let mut download = client.gallery(GALLERY).unwrap().download(options);
+
+        wait_for(&mut download, 1, |kind| {
+            matches!(kind, EventKind::BytesReceived { page: 1, .. })
+        })
+        .await;
+        // the page hangs, so it is still in flight when the grace period ends
+        client.cancel();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.abort();
+        let summary = download.finish().await;
+
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].error.kind(), &ErrorKind::Cancelled);
+        assert!(files(&gallery_dir(&out)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn finishes_pages_in_flight_when_the_gallery_fails() {
+        let out = OutDir::new("gallery-error");
+        // the last listing page fails while page 1 is downloading,
+        // the other pages are still resolved ahead by the parser
+        let mock = Arc::new(
+            MockTransport::new()
+                .page(EHENTAI, fixture("ehentai/gallery.html"))
+                .delay(&format!("{EHENTAI}?p=2"), Duration::from_millis(300))
+                .page(
+                    "https://e-hentai.org/s/0000001eef/1234567-1",
+                    fixture("ehentai/image.html"),
+                )
+                .stall(EHENTAI_IMAGE, "image"),
+        );
+        let builder = Client::builder().transport(mock).defaults(
+            SiteOptions::new()
+                .timeout(Duration::from_secs(1))
+                .retries(1),
+        );
+        let options = DownloadOptions::new(&out.0).pages("1-5,44".parse().unwrap());
+
+        let (_, summary) = download_with(builder, EHENTAI, options, Mode::Download).await;
+
+        let error = summary.error.expect("gallery should fail");
+        assert_eq!(error.kind(), &ErrorKind::NotFound, "{error:#}");
+        // the page timed out instead of being cut off
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].error.kind(), &ErrorKind::Network);
+        assert!(files(&gallery_dir(&out)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn fails_on_unexpected_gallery_page() {
+        let out = OutDir::new("layout");
+        let mock = Arc::new(MockTransport::new().page(GALLERY, fixture("nhentai/image.html")));
+
+        let (_, summary) = download(
+            mock,
+            SiteOptions::new(),
+            DownloadOptions::new(&out.0),
+            Mode::Download,
+        )
+        .await;
+
+        let error = summary.error.expect("gallery should fail");
+        assert_eq!(
+            error.kind().clone(),
+            ErrorKind::Parse {
+                selector: Some("div#info".into())
+            },
+            "{error:#}"
+        );
+        assert_eq!(summary.pages, 0);
+        assert!(summary.metadata.is_none());
+    }
+
+    #[tokio::test]
+    async fn resolves_without_downloading() {
+        let out = OutDir::new("resolve");
+        let mock = Arc::new(nhentai(1..=24));
+        let options = DownloadOptions::new(&out.0).pages("1,3".parse().unwrap());
+
+        let (_, summary) = download(
+            Arc::clone(&mock),
+            SiteOptions::new(),
+            options,
+            Mode::Resolve,
+        )
+        .await;
+
+        let urls = summary
+            .resolved
+            .iter()
+            .map(|page| (page.page, page.url.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(urls, [(1, image(1)), (3, image(3))]);
+        assert_eq!(mock.requests(), [GALLERY, FIRST_IMAGE_PAGE]);
+        assert!(!out.0.exists());
+    }
+
+    #[tokio::test]
+    async fn reports_interstitials() {
+        let out = OutDir::new("interstitial");
+        let challenge = "<html><head><title>Just a moment...</title></head></html>";
+        let mock =
+            Arc::new(MockTransport::new().response(GALLERY, StatusCode::FORBIDDEN, challenge));
+
+        let (_, summary) = download(
+            mock,
+            SiteOptions::new(),
+            DownloadOptions::new(&out.0),
+            Mode::Download,
+        )
+        .await;
+
+        let error = summary.error.expect("gallery should fail");
+        assert_eq!(error.interstitial(), Some(Interstitial::Challenge));
+        assert_eq!(error.kind(), &ErrorKind::RateLimited);
+    }
+
+    #[tokio::test]
+    async fn resolves_ehentai_pages_ahead_in_order() {
+        let out = OutDir::new("ehentai");
+        let mock = Arc::new(ehentai().page(EHENTAI_IMAGE, "image"));
+        let builder = Client::builder().transport(Arc::clone(&mock) as _);
+        let options = DownloadOptions::new(&out.0).pages("2,44".parse().unwrap());
+
+        let (events, summary) = download_with(builder, EHENTAI, options, Mode::Download).await;
+
+        assert!(summary.error.is_none(), "{:?}", summary.error);
+        assert!(summary.failed.is_empty());
+        assert_eq!(summary.pages, 2);
+        let dir = out.0.join("[Example Circle] Sample Gallery [English]");
+        assert!(dir.join("0002.jpg").exists());
+        assert!(dir.join("0044.jpg").exists());
+
+        // the listing page without selected images is skipped,
+        // image pages are only fetched ahead, not again by the downloads
+        let requests = mock.requests();
+        assert!(!requests.contains(&format!("{EHENTAI}?p=1")));
+        for page in [
+            "https://e-hentai.org/s/0000003dde/1234567-2",
+            "https://e-hentai.org/s/0000055114/1234567-44",
+        ] {
+            assert_eq!(requests.iter().filter(|url| *url == page).count(), 1);
+        }
+
+        let count = position(&events, |kind| {
+            matches!(kind, EventKind::PageCount { pages: 2 })
+        });
+        let queued = events
+            .iter()
+            .filter_map(|event| match event.kind {
+                EventKind::PageQueued { page, .. } => Some(page),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(queued, [2, 44]);
+        assert!(count < position(&events, |kind| matches!(kind, EventKind::PageQueued { .. })));
+    }
+
+    #[tokio::test]
+    async fn resolves_ehentai_pages_again_after_a_failed_attempt() {
+        let out = OutDir::new("ehentai-retry");
+        let mock = Arc::new(
+            ehentai()
+                .response(EHENTAI_IMAGE, StatusCode::SERVICE_UNAVAILABLE, "")
+                .page(EHENTAI_IMAGE, "image"),
+        );
+        let builder = Client::builder().transport(Arc::clone(&mock) as _);
+        let options = DownloadOptions::new(&out.0).pages("2".parse().unwrap());
+
+        let (_, summary) = download_with(builder, EHENTAI, options, Mode::Download).await;
+
+        assert!(summary.error.is_none(), "{:?}", summary.error);
+        assert!(summary.failed.is_empty());
+        let requests = mock.requests();
+        let image_page = "https://e-hentai.org/s/0000003dde/1234567-2";
+        assert_eq!(requests.iter().filter(|url| *url == image_page).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_the_status_of_gallery_and_image_pages() {
+        let out = OutDir::new("ehentai-status");
+        let image_page = "https://e-hentai.org/s/0000003dde/1234567-2";
+        let count = |mock: &MockTransport, url: &str| {
+            mock.requests()
+                .iter()
+                .filter(|request| *request == url)
+                .count()
+        };
+
+        let missing = Arc::new(MockTransport::new().response(
+            EHENTAI,
+            StatusCode::NOT_FOUND,
+            "<html><body>Key missing, or incorrect key provided.</body></html>",
+        ));
+        let builder = Client::builder().transport(Arc::clone(&missing) as _);
+        let options = DownloadOptions::new(&out.0);
+        let (_, summary) = download_with(builder, EHENTAI, options, Mode::Download).await;
+        let e = summary.error.unwrap();
+        assert_eq!(e.kind(), &ErrorKind::NotFound, "{e:#}");
+        assert_eq!(count(&missing, EHENTAI), 1);
+
+        let unavailable = Arc::new(
+            MockTransport::new()
+                .page(EHENTAI, fixture("ehentai/gallery.html"))
+                .response(image_page, StatusCode::SERVICE_UNAVAILABLE, ""),
+        );
+        let builder = Client::builder().transport(Arc::clone(&unavailable) as _);
+        let options = DownloadOptions::new(&out.0).pages("2".parse().unwrap());
+        let (events, summary) = download_with(builder, EHENTAI, options, Mode::Download).await;
+        assert!(summary.error.is_none(), "{:?}", summary.error);
+        let [failed] = summary.failed.as_slice() else {
+            panic!("expected one failed page: {:?}", summary.failed);
+        };
+        assert_eq!(
+            failed.error.kind().clone(),
+            ErrorKind::Http(StatusCode::SERVICE_UNAVAILABLE)
+        );
+        let retries = events
+            .iter()
+            .filter(|event| {
+                matches!(
+                    &event.kind,
+                    EventKind::PageRetry { kind: ErrorKind::Http(status), .. }
+                        if *status == StatusCode::SERVICE_UNAVAILABLE
+                )
+            })
+            .count();
+        assert_eq!(retries, SiteOptions::new().attempts() - 1);
+        // resolved ahead once, then once per attempt
+        assert_eq!(
+            count(&unavailable, image_page),
+            1 + SiteOptions::new().attempts()
+        );
+    }
+
+    #[tokio::test]
+    async fn lists_galleries_of_tag_pages() {
+        let tag = "https://nhentai.net/tag/example/";
+        let mock = Arc::new(
+            MockTransport::new()
+                .page(tag, fixture("nhentai/listing.html"))
+                .page(
+                    "https://nhentai.net/tag/example/?page=2",
+                    fixture("nhentai/listing_last.html"),
+                ),
+        );
+        let client = Client::builder().transport(Arc::clone(&mock) as _).build();
+        let list = |options: ListingOptions| {
+            let client = client.clone();
+            async move {
+                let galleries = client.list(tag, options).await.unwrap();
+                galleries
+                    .into_iter()
+                    .map(|gallery| gallery.url)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert!(client.is_listing(tag));
+        assert!(!client.is_listing(GALLERY));
+        assert_eq!(list(ListingOptions::new()).await.len(), 6);
+
+        let english = list(ListingOptions::new().language(Some("English"))).await;
+        assert_eq!(
+            english,
+            [
+                "https://nhentai.net/g/400003/",
+                "https://nhentai.net/g/400001/",
+                "https://nhentai.net/g/300002/",
+            ]
+        );
+
+        // enough galleries on the first page, the second isn't read
+        let requests = mock.requests().len();
+        let first = list(ListingOptions::new().max_galleries(Some(2))).await;
+        assert_eq!(
+            first,
+            [
+                "https://nhentai.net/g/400004/",
+                "https://nhentai.net/g/400003/"
+            ]
+        );
+        assert_eq!(mock.requests().len(), requests + 1);
+        assert_eq!(
+            list(ListingOptions::new().max_pages(Some(1))).await.len(),
+            4
+        );
+
+        let e = client
+            .list(GALLERY, ListingOptions::new())
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), &ErrorKind::Unsupported);
+        assert!(client
+            .list("https://nhentai.net/tag/missing/", ListingOptions::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn keeps_listed_galleries_when_a_later_page_fails() {
+        let tag = "https://nhentai.net/tag/example/";
+        let mock = MockTransport::new()
+            .page(tag, fixture("nhentai/listing.html"))
+            .error(
+                "https://nhentai.net/tag/example/?page=2",
+                "connection reset",
+            );
+        let client = Client::builder().transport(Arc::new(mock)).build();
+
+        let galleries = client.list(tag, ListingOptions::new()).await.unwrap();
+        assert_eq!(galleries.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn follows_newer_versions() {
+        let out = OutDir::new("latest");
+        let old = "https://e-hentai.org/g/1111111/aaaaaaaaaa/";
+        let mock = ehentai()
+            .page(old, fixture("ehentai/gallery_updated.html"))
+            .page(EHENTAI_IMAGE, "image");
+        let client = Client::builder().transport(Arc::new(mock)).build();
+
+        let newest = client.gallery(old).unwrap().latest().await.unwrap();
+        assert_eq!(newest.url(), EHENTAI);
+        assert_eq!(newest.redirected_from().as_deref(), Some(old));
+        // already the newest
+        let again = newest.latest().await.unwrap();
+        assert_eq!(again.url(), EHENTAI);
+        assert_eq!(again.redirected_from().as_deref(), Some(old));
+        let current = client.gallery(EHENTAI).unwrap().latest().await.unwrap();
+        assert!(current.redirected_from().is_none());
+
+        let options = DownloadOptions::new(&out.0).pages("2".parse().unwrap());
+        let summary = newest.resolve(options).finish().await;
+        assert!(summary.error.is_none(), "{:?}", summary.error);
+        let metadata = summary.metadata.unwrap();
+        assert_eq!(metadata.url, EHENTAI);
+        assert_eq!(metadata.redirected_from.as_deref(), Some(old));
+        assert_eq!(metadata.title, "[Example Circle] Sample Gallery [English]");
+    }
+
+    #[tokio::test]
+    async fn batches_gallery_details() {
+        let out = OutDir::new("gdata");
+        let api = "https://api.e-hentai.org/api.php";
+        let other = "https://e-hentai.org/g/7654321/fedcba9876/";
+        let mock = Arc::new(
+            ehentai()
+                .page(EHENTAI_IMAGE, "image")
+                .page(api, fixture("ehentai/gdata.json")),
+        );
+        let client = Client::builder().transport(Arc::clone(&mock) as _).build();
+        let mut subscription = client.subscribe();
+
+        client.prefetch_details([EHENTAI, other, GALLERY]);
+        let options = DownloadOptions::new(&out.0).pages("2,44".parse().unwrap());
+        let summary = client
+            .gallery(EHENTAI)
+            .unwrap()
+            .resolve(options)
+            .finish()
+            .await;
+
+        assert!(summary.error.is_none(), "{:?}", summary.error);
+        let details = summary.metadata.unwrap().details;
+        assert_eq!(details.category.as_deref(), Some("Doujinshi"));
+        assert_eq!(details.pages, Some(45));
+
+        // both e-hentai galleries in one call, none for nhentai nor the download
+        let bodies = mock.bodies();
+        assert_eq!(bodies.len(), 1);
+        let request = serde_json::from_slice::<serde_json::Value>(&bodies[0]).unwrap();
+        assert_eq!(request["method"], "gdata");
+        assert_eq!(
+            request["gidlist"],
+            serde_json::json!([[1234567, "0123456789"], [7654321, "fedcba9876"]])
+        );
+
+        let mut counts = Vec::new();
+        while let Some(event) = subscription.try_recv() {
+            if let EventKind::PageCount { pages } = event.kind {
+                counts.push(pages);
+            }
+        }
+        assert_eq!(counts, [2]);
+    }
+
+    #[tokio::test]
+    async fn reports_malformed_pages() {
+        let gallery = fixture("nhentai/gallery.html");
+        let image_page = fixture("nhentai/image.html");
+        let cases = [
+            (
+                "truncated gallery",
+                fixture("malformed/nhentai_truncated.html"),
+                image_page.clone(),
+            ),
+            (
+                "pages count",
+                fixture("malformed/nhentai_pages.html"),
+                image_page.clone(),
+            ),
+            (
+                "binary gallery",
+                vec![0xff, 0x00, 0xfe, b'<', 0x80],
+                image_page,
+            ),
+            (
+                "image without extension",
+                gallery.clone(),
+                fixture("malformed/nhentai_image_no_extension.html"),
+            ),
+            (
+                "invalid image url",
+                gallery,
+                fixture("malformed/nhentai_image_bad_src.html"),
+            ),
+        ];
+
+        for (name, gallery, image_page) in cases {
+            let out = OutDir::new("malformed");
+            let mock = Arc::new(
+                MockTransport::new()
+                    .page(GALLERY, gallery)
+                    .page(FIRST_IMAGE_PAGE, image_page),
+            );
+
+            let (events, summary) = download(
+                mock,
+                SiteOptions::new(),
+                DownloadOptions::new(&out.0),
+                Mode::Download,
+            )
+            .await;
+
+            let error = summary.error.expect(name);
+            assert!(
+                matches!(error.kind().clone(), ErrorKind::Parse { .. }),
+                "{name}: {error:#}"
+            );
+            assert!(
+                matches!(
+                    events.last().unwrap().kind,
+                    EventKind::GalleryFinished {
+                        error_kind: Some(ErrorKind::Parse { .. }),
+                        ..
+                    }
+                ),
+                "{name}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dumps_pages_that_fail_to_parse() {
+        let out = OutDir::new("dump");
+        let dump = out.0.join("dump");
+        let mock = Arc::new(MockTransport::new().page(GALLERY, fixture("nhentai/image.html")));
+        let builder = Client::builder()
+            .transport(mock)
+            .debug_dump(Some(dump.clone()));
+
+        let (_, summary) = download_with(
+            builder,
+            GALLERY,
+            DownloadOptions::new(&out.0),
+            Mode::Download,
+        )
+        .await;
+
+        let error = summary.error.expect("gallery should fail");
+        assert!(matches!(error.kind().clone(), ErrorKind::Parse { .. }));
+        let mut files = std::fs::read_dir(&dump)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files.len(), 2, "{files:?}");
+        assert!(format!("{error:#}").contains(&format!("page saved to {:?}", files[0])));
+        assert_eq!(
+            std::fs::read(&files[0]).unwrap(),
+            fixture("nhentai/image.html")
+        );
+        let report = std::fs::read_to_string(&files[1]).unwrap();
+        assert!(report.starts_with(&format!("url: {GALLERY}\nsite: Nhentai\n")));
+        assert!(
+            report.contains("selector not found: \"div#info\""),
+            "{report}"
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! what went wrong: inside the crate an [`ErrorKind`] in the chain of `anyhow` errors,
+//! at the public api an [`Error`] carrying it
+
+use std::fmt;
+
+use anyhow::anyhow;
+use hyper::{StatusCode, Uri};
+
+/// category of an error, see [`Error::kind`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// connecting, the proxy, TLS, or a timeout waiting for the site
+    Network,
+    /// the site answered with an error status not covered by another kind
+    Http(StatusCode),
+    /// the gallery or page doesn't exist, or was removed
+    NotFound,
+    /// the page doesn't have the layout the downloader expects;
+    /// `selector` is the chain that didn't match, if that's what failed
+    Parse {
+        selector: Option<String>,
+    },
+    /// no supported site matches the url given to [`crate::Client::gallery`]
+    Unsupported,
+    /// the site asks to slow down, with a status or a bot challenge
+    RateLimited,
+    /// the page needs logging in or accepting a content warning,
+    /// see [`crate::SiteOptions::cookies`]
+    AuthRequired,
+    /// reading or writing local files
+    Io,
+    /// the page was interrupted by [`crate::Client::abort`]
+    Cancelled,
+    Other,
+}
+
+impl ErrorKind {
+    /// the kind attached closest to the top of `error`'s chain, an [`Interstitial`]
+    /// comes with its kind and an [`Error`] wrapped in `error` has one;
+    /// [`ErrorKind::Io`] for untagged io errors and [`ErrorKind::Other`] for the rest
+    pub fn of(error: &anyhow::Error) -> ErrorKind {
+        // `downcast_ref` looks at the outermost contexts first
+        if let Some(kind) = error.downcast_ref::<ErrorKind>() {
+            kind.clone()
+        } else if let Some(error) = error.downcast_ref::<Error>() {
+            error.kind.clone()
+        } else if let Some(interstitial) = error.downcast_ref::<Interstitial>() {
+            interstitial.kind()
+        } else if error.downcast_ref::<std::io::Error>().is_some() {
+            ErrorKind::Io
+        } else {
+            ErrorKind::Other
+        }
+    }
+
+    /// [`ErrorKind::NotFound`], [`ErrorKind::RateLimited`] and
+    /// [`ErrorKind::AuthRequired`] for their statuses, [`ErrorKind::Http`] otherwise
+    pub fn from_status(status: StatusCode) -> ErrorKind {
+        match status {
+            StatusCode::NOT_FOUND | StatusCode::GONE => ErrorKind::NotFound,
+            StatusCode::TOO_MANY_REQUESTS => ErrorKind::RateLimited,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ErrorKind::AuthRequired,
+            status => ErrorKind::Http(status),
+        }
+    }
+
+    /// whether another attempt can succeed where this one failed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ErrorKind::Network | ErrorKind::RateLimited | ErrorKind::Other => true,
+            ErrorKind::Http(status) => {
+                status.is_server_error() || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            ErrorKind::NotFound
+            | ErrorKind::Parse { .. }
+            | ErrorKind::Unsupported
+            | ErrorKind::AuthRequired
+            | ErrorKind::Io
+            | ErrorKind::Cancelled => false,
+        }
+    }
+
+    /// snake case name, e.g. `rate_limited`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Network => "network",
+            ErrorKind::Http(_) => "http",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Parse { .. } => "parse",
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::AuthRequired => "auth_required",
+            ErrorKind::Io => "io",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Network => f.write_str("network error"),
+            ErrorKind::Http(status) => write!(f, "http status {status}"),
+            ErrorKind::NotFound => f.write_str("not found"),
+            ErrorKind::Parse { .. } => f.write_str("unexpected page layout"),
+            ErrorKind::Unsupported => f.write_str("unsupported url"),
+            ErrorKind::RateLimited => f.write_str("rate limited by the site"),
+            ErrorKind::AuthRequired => f.write_str("login required"),
+            ErrorKind::Io => f.write_str("i/o error"),
+            ErrorKind::Cancelled => f.write_str("cancelled"),
+            ErrorKind::Other => f.write_str("error"),
+        }
+    }
+}
+
+impl std::error::Error for ErrorKind {}
+
+/// error of the public api: what went wrong and the chain of its causes;
+/// `{}` prints the outermost message, `{:#}` the whole chain
+pub struct Error {
+    kind: ErrorKind,
+    source: anyhow::Error,
+}
+
+/// result of the public api
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// e.g. for the errors of a [`crate::Transport`]
+    pub fn new<E>(kind: ErrorKind, source: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Self {
+            kind,
+            source: anyhow::Error::new(source),
+        }
+    }
+
+    /// an error without a source
+    pub fn msg(kind: ErrorKind, message: impl fmt::Display) -> Self {
+        Self {
+            kind,
+            source: anyhow!("{message}"),
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// the page the site answered with instead of the expected one, if any
+    pub fn interstitial(&self) -> Option<Interstitial> {
+        self.source.downcast_ref::<Interstitial>().copied()
+    }
+
+    /// `error` with the kind found in its chain
+    pub(crate) fn from_anyhow(error: anyhow::Error) -> Self {
+        Self {
+            kind: ErrorKind::of(&error),
+            source: error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{:#}", self.source)
+        } else {
+            write!(f, "{}", self.source)
+        }
+    }
+}
+
+/// the chain of causes, like `anyhow` prints it
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.source, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.source()
+    }
+}
+
+/// `failed to get {url}` with the kind of `status`, or of the interstitial `page` is
+pub(crate) fn status_error(url: &Uri, status: StatusCode, page: &[u8]) -> anyhow::Error {
+    let e = anyhow!("status code: {status}");
+    let e = match Interstitial::detect(page) {
+        Some(interstitial) => interstitial.attach(e),
+        None => e.context(ErrorKind::from_status(status)),
+    };
+    e.context(format!("failed to get {url}"))
+}
+
+/// a chain of selectors, joined with ` > `, matched nothing;
+/// becomes the selector of [`ErrorKind::Parse`]
+#[derive(Debug)]
+pub(crate) struct SelectorNotFound(pub String);
+
+impl fmt::Display for SelectorNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "selector not found: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for SelectorNotFound {}
+
+/// the site answered with a page standing in for the expected one,
+/// more precise than its [`ErrorKind`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interstitial {
+    /// a bot check such as Cloudflare's "Just a moment..."
+    Challenge,
+    /// the gallery is behind a warning that has to be accepted first
+    ContentWarning,
+    /// the gallery was taken down
+    Removed,
+    /// the page is only shown to logged in users, see [`crate::SiteOptions::cookies`]
+    LoginRequired,
+}
+
+impl Interstitial {
+    /// marks `error` with the interstitial and its kind, so that the kind wins over
+    /// the ones attached below like any other kind attached later
+    pub(crate) fn attach(self, error: anyhow::Error) -> anyhow::Error {
+        error.context(self).context(self.kind())
+    }
+
+    pub fn kind(self) -> ErrorKind {
+        match self {
+            Self::Challenge => ErrorKind::RateLimited,
+            Self::ContentWarning | Self::LoginRequired => ErrorKind::AuthRequired,
+            Self::Removed => ErrorKind::NotFound,
+        }
+    }
+
+    const PATTERNS: &'static [(Self, &'static [&'static str])] = &[
+        (
+            Self::Challenge,
+            &[
+                "<title>just a moment...</title>",
+                "<title>attention required! | cloudflare</title>",
+                "/cdn-cgi/challenge-platform/",
+                "cf-browser-verification",
+                "<title>ddos-guard</title>",
+            ],
+        ),
+        (
+            Self::ContentWarning,
+            &[
+                "<h1>content warning</h1>",
+                "this gallery has been flagged as offensive",
+            ],
+        ),
+        (
+            Self::Removed,
+            &[
+                "this gallery has been removed",
+                "has been removed or is unavailable",
+                "this gallery is unavailable due to",
+                "<title>gallery not found",
+            ],
+        ),
+        (
+            Self::LoginRequired,
+            &["this page requires you to log on", "you must be logged in"],
+        ),
+    ];
+
+    /// recognizes the known interstitials, only meant for pages that failed to parse
+    pub(crate) fn detect(page: &[u8]) -> Option<Self> {
+        let page = String::from_utf8_lossy(page).to_lowercase();
+        Self::PATTERNS
+            .iter()
+            .find(|(_, patterns)| patterns.iter().any(|pattern| page.contains(pattern)))
+            .map(|(kind, _)| *kind)
+    }
+}
+
+impl fmt::Display for Interstitial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Challenge => "blocked by a bot challenge page",
+            Self::ContentWarning => "the gallery is behind a content warning",
+            Self::Removed => "the gallery was removed",
+            Self::LoginRequired => "the page requires logging in",
+        })
+    }
+}
+
+impl std::error::Error for Interstitial {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_kind_in_the_chain() {
+        let url = Uri::from_static("https://example.com/g/1/");
+
+        let e = anyhow!("connection reset").context(ErrorKind::Network);
+        assert_eq!(
+            ErrorKind::of(&e.context("failed to download")),
+            ErrorKind::Network
+        );
+
+        let e = status_error(&url, StatusCode::NOT_FOUND, b"");
+        assert_eq!(ErrorKind::of(&e), ErrorKind::NotFound);
+        let e = status_error(&url, StatusCode::BAD_GATEWAY, b"");
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Http(StatusCode::BAD_GATEWAY));
+        assert!(ErrorKind::of(&e).is_retryable());
+        let challenge = b"<title>Just a moment...</title>";
+        let e = status_error(&url, StatusCode::FORBIDDEN, challenge);
+        assert_eq!(ErrorKind::of(&e), ErrorKind::RateLimited);
+
+        // the outermost kind wins, e.g. cancelling a page that was failing anyway
+        let e = status_error(&url, StatusCode::NOT_FOUND, b"").context(ErrorKind::Cancelled);
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Cancelled);
+
+        let io = std::io::Error::new(std::io::ErrorKind::StorageFull, "disk full");
+        let e = anyhow::Error::new(io).context("failed to write");
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Io);
+        assert!(!ErrorKind::of(&e).is_retryable());
+
+        assert_eq!(ErrorKind::of(&anyhow!("something")), ErrorKind::Other);
+
+        // an interstitial found later wins over a kind attached below it
+        let e = anyhow!("no images").context(ErrorKind::Parse { selector: None });
+        let e = Interstitial::LoginRequired.attach(e);
+        assert_eq!(ErrorKind::of(&e), Interstitial::LoginRequired.kind());
+    }
+
+    #[test]
+    fn keeps_the_kind_and_the_chain() {
+        let url = Uri::from_static("https://example.com/g/1/");
+        let challenge = b"<title>Just a moment...</title>";
+        let e = status_error(&url, StatusCode::FORBIDDEN, challenge);
+        let e = Error::from_anyhow(e.context("failed to download"));
+
+        assert_eq!(e.kind(), &ErrorKind::RateLimited);
+        assert_eq!(e.interstitial(), Some(Interstitial::Challenge));
+        assert_eq!(e.to_string(), "failed to download");
+        assert!(
+            format!("{e:#}").ends_with("status code: 403 Forbidden"),
+            "{e:#}"
+        );
+
+        // through the cli's anyhow errors
+        let e = anyhow::Error::new(e).context("listing failed");
+        assert_eq!(ErrorKind::of(&e), ErrorKind::RateLimited);
+
+        let e = Error::msg(ErrorKind::Unsupported, "no downloader");
+        assert_eq!(e.kind(), &ErrorKind::Unsupported);
+        assert_eq!(e.interstitial(), None);
+    }
+
+    #[test]
+    fn detects_interstitials() {
+        let pages: &[(&str, Option<Interstitial>)] = &[
+            (
+                "<html><head><title>Just a moment...</title></head></html>",
+                Some(Interstitial::Challenge),
+            ),
+            (
+                r#"<script src="/cdn-cgi/challenge-platform/h/b/orchestrate/chl_page/v1"></script>"#,
+                Some(Interstitial::Challenge),
+            ),
+            (
+                "<div><h1>Content Warning</h1><p>This gallery has been flagged as Offensive For Everyone.</p></div>",
+                Some(Interstitial::ContentWarning),
+            ),
+            (
+                "<p>This gallery has been removed or is unavailable.</p>",
+                Some(Interstitial::Removed),
+            ),
+            (
+                "<p>This page requires you to log on.</p>",
+                Some(Interstitial::LoginRequired),
+            ),
+            ("<html><body><div id=\"info\"></div></body></html>", None),
+        ];
+
+        for (page, expected) in pages {
+            assert_eq!(Interstitial::detect(page.as_bytes()), *expected, "{page}");
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+use crate::client::Metadata;
+use crate::error::ErrorKind;
+
+/// identifies one download of a gallery within a [`crate::Client`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GalleryId(pub(crate) u64);
+
+impl GalleryId {
+    /// numbers are assigned in download order starting from 0
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for GalleryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Event {
+    pub gallery: GalleryId,
+    pub kind: EventKind,
+}
+
+/// pages are identified by their 1-based index in the gallery
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum EventKind {
+    /// a gallery download or resolution started
+    GalleryDiscovered { url: String, site: &'static str },
+    /// the gallery title is known and the output directory is chosen
+    Metadata(Metadata),
+    /// number of pages the gallery will queue, when the site tells before they are all found
+    PageCount { pages: usize },
+    /// the parser found one more page to download
+    PageQueued { page: usize, url: String },
+    /// looking up the image url of the page
+    PageResolving { page: usize },
+    /// the image request is being sent, `file_name` is relative to the gallery directory
+    PageStarted { page: usize, file_name: PathBuf },
+    /// the server announced the image size with `Content-Length`
+    PageSize { page: usize, bytes: u64 },
+    /// a chunk of the image was written
+    BytesReceived { page: usize, len: usize },
+    /// an attempt failed and the page will be tried again
+    PageRetry {
+        page: usize,
+        attempt: usize,
+        kind: ErrorKind,
+        error: String,
+    },
+    /// the page was given up on, after all retries or an error retrying can't fix
+    PageFailed {
+        page: usize,
+        kind: ErrorKind,
+        error: String,
+    },
+    /// the image is written to disk, already was there, or its url is resolved
+    PageDone { page: usize },
+    /// nothing else will be reported for the gallery
+    GalleryFinished {
+        pages: usize,
+        failed: usize,
+        error_kind: Option<ErrorKind>,
+        error: Option<String>,
+    },
+}
+
+impl EventKind {
+    /// the page the event is about
+    fn page(&self) -> Option<usize> {
+        match *self {
+            EventKind::PageQueued { page, .. }
+            | EventKind::PageResolving { page }
+            | EventKind::PageStarted { page, .. }
+            | EventKind::PageSize { page, .. }
+            | EventKind::BytesReceived { page, .. }
+            | EventKind::PageRetry { page, .. }
+            | EventKind::PageFailed { page, .. }
+            | EventKind::PageDone { page } => Some(page),
+            _ => None,
+        }
+    }
+}
+
+/// receiving end of [`crate::Client::subscribe`]
+pub struct Subscription {
+    queue: Arc<Queue>,
+}
+
+impl Subscription {
+    /// `None` once the client and every download are gone
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.queue.pop() {
+                return Some(event);
+            }
+            if self.queue.closed.load(Ordering::Acquire) {
+                // pushed right before closing
+                return self.queue.pop();
+            }
+            self.queue.notify.notified().await;
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<Event> {
+        self.queue.pop()
+    }
+}
+
+/// events a subscriber hasn't received yet
+#[derive(Default)]
+struct Queue {
+    events: Mutex<VecDeque<Event>>,
+    /// nothing will be pushed anymore
+    closed: AtomicBool,
+    notify: Notify,
+}
+
+impl Queue {
+    fn push(&self, event: Event) {
+        let Ok(mut events) = self.events.lock() else {
+            return;
+        };
+
+        // a subscriber that lags behind would otherwise pile up an event per chunk,
+        // so chunks are added to the last one of the page still queued, unless
+        // another event of the page is queued after it
+        if let EventKind::BytesReceived { page, len } = event.kind {
+            let last = events
+                .iter_mut()
+                .rev()
+                .find(|queued| queued.gallery == event.gallery && queued.kind.page() == Some(page));
+            if let Some(Event {
+                kind: EventKind::BytesReceived { len: queued, .. },
+                ..
+            }) = last
+            {
+                *queued += len;
+                return;
+            }
+        }
+
+        events.push_back(event);
+        drop(events);
+        self.notify.notify_one();
+    }
+
+    fn pop(&self) -> Option<Event> {
+        self.events.lock().ok()?.pop_front()
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+}
+
+/// fans every event out to all subscribers, dropped subscriptions are forgotten
+#[derive(Default)]
+pub(crate) struct EventBus {
+    /// `None` filter receives events of every gallery
+    subscribers: Mutex<Vec<(Option<GalleryId>, Arc<Queue>)>>,
+}
+
+impl EventBus {
+    pub(crate) fn subscribe(&self, gallery: Option<GalleryId>) -> Subscription {
+        let queue = Arc::new(Queue::default());
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push((gallery, Arc::clone(&queue)));
+        }
+
+        Subscription { queue }
+    }
+
+    pub(crate) fn emit(&self, gallery: GalleryId, kind: EventKind) {
+        let Ok(mut subscribers) = self.subscribers.lock() else {
+            return;
+        };
+
+        let finished = matches!(kind, EventKind::GalleryFinished { .. });
+
+        subscribers.retain(|(filter, queue)| {
+            // the subscription was dropped
+            if Arc::strong_count(queue) == 1 {
+                return false;
+            }
+            if filter.is_some_and(|filter| filter != gallery) {
+                return true;
+            }
+            queue.push(Event {
+                gallery,
+                kind: kind.clone(),
+            });
+
+            // closes the per-gallery subscription
+            if finished && filter.is_some() {
+                queue.close();
+                return false;
+            }
+            true
+        });
+    }
+}
+
+impl Drop for EventBus {
+    fn drop(&mut self) {
+        if let Ok(subscribers) = self.subscribers.get_mut() {
+            for (_, queue) in subscribers.iter() {
+                queue.close();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(page: usize, len: usize) -> EventKind {
+        EventKind::BytesReceived { page, len }
+    }
+
+    /// queued events, compared by their debug output
+    fn drain(subscription: &mut Subscription) -> Vec<(GalleryId, String)> {
+        std::iter::from_fn(|| subscription.try_recv())
+            .map(|event| (event.gallery, format!("{:?}", event.kind)))
+            .collect()
+    }
+
+    fn expected(events: &[(u64, EventKind)]) -> Vec<(GalleryId, String)> {
+        events
+            .iter()
+            .map(|(gallery, kind)| (GalleryId(*gallery), format!("{kind:?}")))
+            .collect()
+    }
+
+    #[test]
+    fn merges_the_chunks_of_lagging_subscribers() {
+        let bus = EventBus::default();
+        let mut subscription = bus.subscribe(None);
+        let gallery = GalleryId(0);
+
+        bus.emit(gallery, bytes(1, 10));
+        assert_eq!(drain(&mut subscription), expected(&[(0, bytes(1, 10))]));
+
+        for _ in 0..1000 {
+            bus.emit(gallery, bytes(1, 10));
+            bus.emit(gallery, bytes(2, 1));
+        }
+        bus.emit(gallery, EventKind::PageDone { page: 1 });
+        bus.emit(gallery, bytes(2, 1));
+        bus.emit(gallery, bytes(1, 5));
+        bus.emit(GalleryId(1), bytes(1, 7));
+
+        let merged = expected(&[
+            (0, bytes(1, 10000)),
+            (0, bytes(2, 1001)),
+            (0, EventKind::PageDone { page: 1 }),
+            // the page ended, the next chunk isn't counted for it
+            (0, bytes(1, 5)),
+            (1, bytes(1, 7)),
+        ]);
+        assert_eq!(drain(&mut subscription), merged);
+    }
+
+    #[tokio::test]
+    async fn closes_gallery_subscriptions_when_finished() {
+        let bus = EventBus::default();
+        let mut gallery = bus.subscribe(Some(GalleryId(1)));
+        let mut all = bus.subscribe(None);
+        let dropped = bus.subscribe(None);
+        drop(dropped);
+
+        bus.emit(GalleryId(0), EventKind::PageDone { page: 1 });
+        bus.emit(GalleryId(1), EventKind::PageDone { page: 2 });
+        let finished = EventKind::GalleryFinished {
+            pages: 2,
+            failed: 0,
+            error_kind: None,
+            error: None,
+        };
+        bus.emit(GalleryId(1), finished);
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+
+        let next = gallery.recv().await.unwrap();
+        assert_eq!(next.gallery, GalleryId(1));
+        assert!(matches!(next.kind, EventKind::PageDone { page: 2 }));
+        let next = gallery.recv().await.unwrap();
+        assert!(matches!(next.kind, EventKind::GalleryFinished { .. }));
+        assert!(gallery.recv().await.is_none());
+
+        bus.emit(GalleryId(0), EventKind::PageDone { page: 3 });
+        drop(bus);
+        let mut pages = Vec::new();
+        while let Some(event) = all.recv().await {
+            pages.extend(event.kind.page());
+        }
+        assert_eq!(pages, [1, 2, 3]);
+    }
+}
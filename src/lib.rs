@@ -0,0 +1,32 @@
+//! Manga downloader
+//!
+//! Start with [`Client`]: it matches gallery urls to the supported sites and
+//! downloads them in background, reporting progress as [`Event`]s.
+
+mod client;
+mod config;
+mod downloaders;
+mod engine;
+mod error;
+mod event;
+mod page_range;
+mod rate_limit;
+mod recording;
+mod request;
+mod site_options;
+#[cfg(test)]
+mod test_server;
+mod transport;
+
+pub use client::{
+    Client, ClientBuilder, Download, DownloadOptions, FailedPage, GalleryDetails, GalleryHandle,
+    ListedGallery, ListingOptions, Metadata, ResolvedPage, Summary,
+};
+pub use downloaders::SiteDefinition;
+pub use error::{Error, ErrorKind, Interstitial, Result};
+pub use event::{Event, EventKind, GalleryId, Subscription};
+pub use page_range::PageRange;
+pub use recording::{RecordingTransport, ReplayTransport};
+pub use request::HttpTransport;
+pub use site_options::SiteOptions;
+pub use transport::{Body, MockTransport, Transport};
@@ -1,212 +1,817 @@
-mod args;
-mod config;
-mod downloaders;
-mod progress;
-mod request;
+mod cli;
 
-use std::path::Path;
+use std::io::IsTerminal;
 use std::path::PathBuf;
+use std::process::ExitCode;
 use std::sync::Arc;
-use std::thread;
 
-use anyhow::anyhow;
 use clap::Parser;
-use http_body_util::BodyExt;
-use tokio::fs;
-use tokio::io::AsyncWriteExt;
-use tokio::sync::{mpsc, Semaphore};
+use indicatif::MultiProgress;
+use tokio::sync::{oneshot, Semaphore};
 use tokio::task::JoinSet;
+use tracing::{info, warn};
 
 use anyhow::{bail, Context, Result};
 
-use args::Args;
-use downloaders::{downloaders, Downloader};
-use progress::progress_bar;
-use request::request;
+use hdl::{
+    Client, DownloadOptions, GalleryHandle, HttpTransport, ListingOptions, PageRange,
+    RecordingTransport, ReplayTransport, Transport,
+};
+
+use cli::args::{Args, Command, ConfigCommand, ProgressFormat};
+use cli::export::{self, ExportFormat};
+use cli::failed;
+use cli::history::{self, History};
+use cli::json::json_progress;
+use cli::plain::plain_progress;
+use cli::progress::progress_bar;
+use cli::settings::Settings;
+use cli::summary::{self, ExitStatus, GalleryReport, Outcome};
+
+/// state shared by every gallery of the run
+#[derive(Clone)]
+struct Session {
+    client: Client,
+    settings: Arc<Settings>,
+    history: Option<Arc<History>>,
+    /// resolve image urls and print them instead of downloading
+    dry_run: bool,
+    export_format: ExportFormat,
+    /// ignore the history and already downloaded files
+    force: bool,
+    /// download the newest revision of updated galleries
+    latest: bool,
+}
+
+/// a gallery to download
+#[derive(Clone)]
+pub(crate) struct Job {
+    url: String,
+    pages: PageRange,
+    /// `--pages` of the run that asked for the gallery, `None` for every page;
+    /// `pages` only differs when retrying failed pages
+    selection: Option<PageRange>,
+}
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let args = Args::parse();
+    let settings = Settings::load(args.config.as_deref(), &args)?;
 
-    let url = args.url.as_ref();
+    if let Some(Command::Config(ConfigCommand::Show)) = &args.command {
+        // catches unknown sites and invalid proxies
+        settings.client(Client::builder())?;
+        print!("{}", settings.show()?);
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    for extractor in downloaders().iter() {
-        if !extractor.is_gallery_match(url) {
-            continue;
-        }
+    let mut jobs = args
+        .gallery_urls()?
+        .into_iter()
+        .map(|url| Job {
+            url,
+            pages: args.pages.clone().unwrap_or_default(),
+            selection: args.pages.clone(),
+        })
+        .collect::<Vec<_>>();
+    if let Some(path) = &args.retry_failed {
+        jobs.extend(failed::read(path)?);
+    }
+    if jobs.is_empty() {
+        bail!("nothing to download");
+    }
 
-        return start_download(Arc::clone(extractor), &args).await;
+    // shared with the logger so log lines don't tear the bars
+    let bars = MultiProgress::new();
+    cli::log::init(
+        args.verbose,
+        args.quiet,
+        args.log_file.as_deref(),
+        bars.clone(),
+    )?;
+
+    let transport: Arc<dyn Transport> = match (&args.replay, &args.record) {
+        (Some(path), _) => Arc::new(ReplayTransport::open(path)?),
+        (None, Some(path)) => Arc::new(
+            RecordingTransport::create(Arc::new(HttpTransport), path)?
+                .full_images(args.record_images),
+        ),
+        (None, None) => Arc::new(HttpTransport),
+    };
+    let client = settings.client(
+        Client::builder()
+            .transport(transport)
+            .debug_dump(args.debug_dump.clone()),
+    )?;
+    if client.rate_limit().is_some() {
+        spawn_rate_signal_handler(client.clone())?;
+    }
+    spawn_cancel_signal_handler(client.clone(), bars.clone())?;
+
+    // search, tag, artist and group urls become the galleries they list
+    let listing = ListingOptions::new()
+        .max_galleries(args.max_galleries)
+        .max_pages(args.listing_pages)
+        .language(args.language.as_deref());
+    let (jobs, failed_listings) = expand_listings(&client, jobs, listing).await;
+    if jobs.is_empty() && failed_listings.is_empty() {
+        eprintln!("no galleries found");
+        return Ok(ExitCode::SUCCESS);
     }
 
-    bail!(format!("downloader not found for: {:?}", url))
-}
+    let (quit_tx, quit_rx) = oneshot::channel();
+    let format = match args.progress {
+        _ if args.quiet => ProgressFormat::Plain,
+        ProgressFormat::Auto if std::io::stderr().is_terminal() => ProgressFormat::Bar,
+        ProgressFormat::Auto => ProgressFormat::Plain,
+        format => format,
+    };
+    let progress = match format {
+        ProgressFormat::Auto | ProgressFormat::Plain => {
+            tokio::spawn(plain_progress(client.subscribe(), args.quiet, quit_rx))
+        }
+        ProgressFormat::Bar => tokio::spawn(progress_bar(
+            client.subscribe(),
+            client.clone(),
+            bars,
+            quit_rx,
+        )),
+        ProgressFormat::Json => {
+            let out = json_output(&args)?;
+            tokio::spawn(json_progress(client.subscribe(), out, quit_rx))
+        }
+    };
 
-async fn start_download(downloader: Arc<dyn Downloader>, args: &Args) -> Result<()> {
-    const BUFF_SZ: usize = 1024;
-    let semaphore = Arc::new(Semaphore::new(args.jobs));
+    let history = match history::default_path() {
+        // a replay must not skip galleries nor record them as downloaded
+        _ if args.replay.is_some() => None,
+        // a dry run lists every page again, e.g. to check a site after a change
+        _ if args.dry_run => None,
+        Some(path) => Some(Arc::new(History::open(&path)?)),
+        None => None,
+    };
 
-    let (parser_tx, mut parser_rx) = mpsc::channel::<downloaders::Msg>(BUFF_SZ);
+    let session = Session {
+        client: client.clone(),
+        settings: Arc::new(settings),
+        history,
+        dry_run: args.dry_run,
+        export_format: args.format,
+        force: args.force,
+        latest: args.latest,
+    };
 
-    let (progress_tx, progress_rx) = std::sync::mpsc::channel::<progress::Msg>();
+    // sites with an api answer for many galleries at once
+    client.prefetch_details(jobs.iter().map(|job| &job.url));
 
-    let mut set = JoinSet::<Result<()>>::new();
+    let galleries = session.settings.galleries();
+    let mut results = failed_listings;
+    results.extend(download_galleries(jobs, galleries, session).await?);
 
-    let name = downloader.name();
+    let _ = quit_tx.send(());
+    progress
+        .await
+        .context("failed to join the progress-bar task")?
+        .context("progress-bar task returned an error")?;
 
-    let progress: thread::JoinHandle<Result<_>> = thread::spawn(|| {
-        progress_bar(progress_rx, name)?;
-        Ok(())
-    });
+    if let Some(path) = &args.failed_report {
+        failed::write(path, &results)?;
+    }
+    let resume = save_resume_state(&client, failed::resume_path(), &results)?;
+
+    let reports = results
+        .into_iter()
+        .map(|(_, report)| report)
+        .collect::<Vec<_>>();
+    if let [report] = reports.as_slice() {
+        if let Some(e) = &report.error {
+            eprintln!("Error: {e:?}");
+        }
+        if report.skipped && !args.quiet {
+            eprintln!(
+                "already downloaded according to the history: {} (use --force to download again)",
+                report.url
+            );
+        }
+        summary::print_failed_pages(report, "");
+    } else {
+        summary::print_summary(&reports);
+    }
+    summary::print_hints(&reports);
 
-    let parser_task = {
-        let downloader = Arc::clone(&downloader);
-        let url = args.url.inner();
+    if client.is_cancelled() {
+        if let Some(path) = resume {
+            eprintln!("interrupted, resume with: hdl --retry-failed {path:?}");
+        }
+        return Ok(ExitStatus::Interrupted.into());
+    }
+    Ok(summary::exit_status(&reports).into())
+}
 
-        tokio::spawn(async move {
-            downloader.start_parser_task(parser_tx, url).await;
-        })
-    };
+/// what an interrupted run didn't download, saved to `path` for `--retry-failed`
+fn save_resume_state(
+    client: &Client,
+    path: Option<PathBuf>,
+    results: &[(Job, GalleryReport)],
+) -> Result<Option<PathBuf>> {
+    match path {
+        Some(path) if client.is_cancelled() => {
+            failed::write(&path, results)?;
+            Ok(Some(path))
+        }
+        _ => Ok(None),
+    }
+}
 
-    let mut id: usize = 0;
-    let mut manga_dir = None;
-
-    while let Some(msg) = parser_rx.recv().await {
-        use downloaders::Msg;
-        match msg {
-            Msg::Title(title) => {
-                let title = title
-                    .replace('/', "_")
-                    .chars()
-                    .take(config::MAX_FILE_NAME_LEN)
-                    .collect::<String>();
-                let dir = args.out_dir.as_ref().join(title);
-                fs::create_dir_all(&dir).await?;
-                manga_dir = Some(Arc::from(dir));
-            }
-            Msg::Images(urls) => {
-                let len = urls.len().try_into()?;
-                progress_tx.send(progress::Msg::IncLen(len))?;
-
-                let out_dir = manga_dir
-                    .as_ref()
-                    .expect("name message should be already received");
-
-                for img in urls {
-                    let permit = semaphore.clone().acquire_owned().await?;
-
-                    let tx = progress_tx.clone();
-                    let downloader = Arc::clone(&downloader);
-                    let out_dir = Arc::clone(out_dir);
-
-                    set.spawn(async move {
-                        let img = &img;
-                        let mut done = false;
-                        // TODO: do this properly
-                        for _ in 0..config::DOWNLOAD_RETRIES {
-                            let tx = tx.clone();
-                            let out_dir = Arc::clone(&out_dir);
-                            let downloader = Arc::clone(&downloader);
-
-                            let timeout =
-                                tokio::time::timeout(config::REQUEST_READ_TIMEOUT, async move {
-                                    download_image(downloader, out_dir, tx, id, img)
-                                        .await
-                                        .with_context(|| {
-                                            format!("failed to download {img:?} (task-id={id})")
-                                        })
-                                })
-                                .await;
-                            if let Ok(res) = timeout {
-                                res?;
-                                done = true;
-                                break;
-                            }
-                        }
-                        drop(permit);
-                        if done {
-                            Ok(())
-                        } else {
-                            bail!("download failed after {} retries", config::DOWNLOAD_RETRIES)
-                        }
-                    });
-
-                    id += 1;
-                }
+/// listing urls replaced by their galleries, with the same pages;
+/// listings that can't be read are reported like failed galleries
+async fn expand_listings(
+    client: &Client,
+    jobs: Vec<Job>,
+    options: ListingOptions,
+) -> (Vec<Job>, Vec<(Job, GalleryReport)>) {
+    let mut expanded = Vec::with_capacity(jobs.len());
+    let mut failed = Vec::new();
+
+    for job in jobs {
+        if !client.is_listing(&job.url) {
+            expanded.push(job);
+            continue;
+        }
+
+        match client.list(&job.url, options.clone()).await {
+            Ok(galleries) => {
+                info!(
+                    url = job.url,
+                    galleries = galleries.len(),
+                    "listing expanded"
+                );
+                expanded.extend(galleries.into_iter().map(|gallery| Job {
+                    url: gallery.url,
+                    ..job.clone()
+                }));
             }
-            Msg::Error(e) => {
-                Err(e).context("error happend in downloader task")?;
+            Err(e) => {
+                let mut report = GalleryReport::new(job.url.clone());
+                report.error = Some(e.into());
+                failed.push((job, report));
             }
         }
     }
 
+    (expanded, failed)
+}
+
+/// download galleries from the queue, `concurrency` of them at the same time
+async fn download_galleries(
+    jobs: Vec<Job>,
+    concurrency: usize,
+    session: Session,
+) -> Result<Vec<(Job, GalleryReport)>> {
+    let concurrency = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut set = JoinSet::<(usize, (Job, GalleryReport))>::new();
+
+    let mut jobs = jobs.into_iter().enumerate();
+    let mut not_started = Vec::new();
+
+    for (gallery, job) in jobs.by_ref() {
+        let permit = tokio::select! {
+            permit = Arc::clone(&concurrency).acquire_owned() => permit?,
+            _ = session.client.cancelled() => {
+                not_started.push((gallery, job));
+                break;
+            }
+        };
+        let session = session.clone();
+
+        set.spawn(async move {
+            let mut report = GalleryReport::new(job.url.clone());
+
+            if let Err(e) = download_gallery(&session, &job, &mut report).await {
+                report.error = Some(e);
+            }
+
+            drop(permit);
+            (gallery, (job, report))
+        });
+    }
+
+    let mut reports = Vec::with_capacity(set.len());
     while let Some(res) = set.join_next().await {
-        if let Err(e) = res.context("failed to join async task")? {
-            eprintln!("async task failed: {e}");
+        reports.push(res.context("failed to join gallery task")?);
+    }
+    for (gallery, job) in not_started.into_iter().chain(jobs) {
+        let mut report = GalleryReport::new(job.url.clone());
+        report.interrupted = true;
+        reports.push((gallery, (job, report)));
+    }
+    reports.sort_by_key(|(gallery, _)| *gallery);
+
+    Ok(reports.into_iter().map(|(_, report)| report).collect())
+}
+
+async fn download_gallery(session: &Session, job: &Job, report: &mut GalleryReport) -> Result<()> {
+    let mut handle = session.client.gallery(&job.url)?;
+    let site = handle.site();
+    report.site = Some(site);
+    if session.latest {
+        handle = handle.latest().await?;
+        if let Some(old) = handle.redirected_from() {
+            report.url = handle.url();
+            // a dry run leaves no trace
+            if !session.dry_run {
+                if let Err(e) = mark_superseded(session, &handle, &old) {
+                    warn!(
+                        error = format!("{e:#}"),
+                        "failed to update download history"
+                    );
+                }
+            }
         }
     }
+    let gallery_id = handle.gallery_id();
+
+    let previous = gallery_id
+        .as_ref()
+        .zip(session.history.as_ref())
+        .and_then(|(id, history)| history.get(site, id))
+        .filter(|_| !session.force);
+
+    if let Some(record) = previous.filter(|r| r.status == history::Status::Complete) {
+        report.skipped = true;
+        report.pages = record.pages;
+        report.out_dir = record.out_dir.clone();
+        return Ok(());
+    }
 
-    parser_task.await?;
-    progress_tx.send(progress::Msg::Quit)?;
-    progress
-        .join()
-        .map_err(|e| anyhow!("progress-bar thread panicked: {:?}", e))
-        .context("failed to join the spawned progress-bar thread")?
-        .context("progress-bar thread returned an error")?;
+    let options = DownloadOptions::new(session.settings.out_dir(site))
+        .pages(job.pages.clone())
+        .overwrite(session.force);
+    // the progress bar follows the events through its own subscription
+    let download = if session.dry_run {
+        handle.resolve(options)
+    } else {
+        handle.download(options)
+    };
+
+    let mut summary = download.finish().await;
+    let resolved = std::mem::take(&mut summary.resolved);
+    report.finish(summary);
+
+    if session.dry_run {
+        export::print(session.export_format, site, report, &resolved)?;
+    } else if let Err(e) = record_history(session, job, site, gallery_id, report) {
+        warn!(
+            error = format!("{e:#}"),
+            "failed to update download history"
+        );
+    }
 
     Ok(())
 }
 
-async fn download_image(
-    downloader: Arc<dyn Downloader>,
-    out_dir: Arc<Path>,
-    tx: std::sync::mpsc::Sender<progress::Msg>,
-    id: usize,
-    url: &hyper::Uri,
+fn record_history(
+    session: &Session,
+    job: &Job,
+    site: &str,
+    gallery_id: Option<String>,
+    report: &GalleryReport,
 ) -> Result<()> {
-    use progress::{Msg, Status, Update};
+    let (Some(history), Some(gallery_id)) = (&session.history, gallery_id) else {
+        return Ok(());
+    };
+    // nothing was written to disk
+    if report.out_dir.is_none() {
+        return Ok(());
+    }
 
-    tx.send(Msg::Update(Update {
-        id,
-        status: Status::ResolvingUrl,
-    }))?;
-    let url = &downloader.resolve_image_url(url).await?;
+    let status = match report.outcome() {
+        // a page selection leaves the rest of the gallery for later
+        Outcome::Succeeded if job.selection.is_some() => history::Status::Partial,
+        Outcome::Succeeded | Outcome::Skipped => history::Status::Complete,
+        Outcome::PartiallySucceeded => history::Status::Partial,
+        Outcome::Failed => history::Status::Failed,
+    };
 
-    let out_dir = out_dir.as_ref();
-    let path = PathBuf::from(url.path());
+    history.append(&history::Record {
+        site: site.to_owned(),
+        gallery_id,
+        url: report.url.clone(),
+        status,
+        out_dir: report.out_dir.clone(),
+        pages: report.pages,
+        time: history::now(),
+        superseded_by: None,
+    })
+}
 
-    let file_name = path.file_name().context("missing file name")?;
-    // TODO: use image number as file name
-    let file_path = out_dir.join(file_name);
+/// the history entry of `old`, if any, points to the newer revision of `handle`
+fn mark_superseded(session: &Session, handle: &GalleryHandle, old: &str) -> Result<()> {
+    let Some(history) = &session.history else {
+        return Ok(());
+    };
+    let site = handle.site();
+    let Some(old_id) = session
+        .client
+        .gallery(old)
+        .ok()
+        .and_then(|old| old.gallery_id())
+    else {
+        return Ok(());
+    };
+    let Some(record) = history.get(site, &old_id) else {
+        return Ok(());
+    };
+    let newer = handle.url();
+    if record.superseded_by.as_ref() == Some(&newer) {
+        return Ok(());
+    }
 
-    tx.send(Msg::Update(Update {
-        id,
-        status: Status::Starting(file_name.to_owned()),
-    }))?;
+    history.append(&history::Record {
+        superseded_by: Some(newer),
+        time: history::now(),
+        ..record.clone()
+    })
+}
 
-    let mut response = request(url).await?;
+/// stdout is kept for `--dry-run`, so json progress needs its own descriptor then
+fn json_output(args: &Args) -> Result<Box<dyn std::io::Write + Send>> {
+    let Some(fd) = args.progress_fd else {
+        if args.dry_run {
+            bail!("--progress json with --dry-run needs --progress-fd");
+        }
+        return Ok(Box::new(std::io::stdout()));
+    };
 
-    let mut file = fs::File::create(&file_path)
-        .await
-        .with_context(|| format!("failed to create file: {file_path:?}"))?;
-
-    let mut downloading_sent = false;
-    while let Some(next) = response.frame().await {
-        if let Some(chunck) = next?.data_ref() {
-            if !downloading_sent {
-                downloading_sent = true;
-                tx.send(Msg::Update(Update {
-                    id,
-                    status: Status::Downloading,
-                }))?;
-            }
-            file.write_all(chunck).await?;
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(format!("/dev/fd/{fd}"))
+        .with_context(|| format!("failed to open file descriptor {fd}"))?;
+    Ok(Box::new(std::io::BufWriter::new(file)))
+}
+
+/// the first SIGINT or SIGTERM stops scheduling pages and aborts the ones in flight
+/// after a grace period, the second one exits right away
+fn spawn_cancel_signal_handler(client: Client, bars: MultiProgress) -> Result<()> {
+    use tokio::signal::unix::{signal, Signal, SignalKind};
+
+    async fn next_signal(interrupt: &mut Signal, terminate: &mut Signal) {
+        tokio::select! {
+            _ = interrupt.recv() => {}
+            _ = terminate.recv() => {}
         }
     }
 
-    tx.send(Msg::Update(Update {
-        id,
-        status: Status::Done,
-    }))?;
+    let mut interrupt = signal(SignalKind::interrupt()).context("failed to listen SIGINT")?;
+    let mut terminate = signal(SignalKind::terminate()).context("failed to listen SIGTERM")?;
+
+    tokio::spawn(async move {
+        next_signal(&mut interrupt, &mut terminate).await;
+        client.cancel();
+        bars.suspend(|| {
+            eprintln!("interrupted, finishing the pages in flight (press Ctrl-C again to quit)")
+        });
+
+        tokio::select! {
+            _ = tokio::time::sleep(cli::config::CANCEL_GRACE_PERIOD) => {
+                client.abort();
+                next_signal(&mut interrupt, &mut terminate).await;
+            }
+            _ = next_signal(&mut interrupt, &mut terminate) => {}
+        }
+
+        let _ = bars.clear();
+        std::process::exit(ExitStatus::Interrupted as i32);
+    });
 
     Ok(())
 }
+
+/// SIGUSR1 halves the rate limit, SIGUSR2 doubles it
+fn spawn_rate_signal_handler(client: Client) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut slower = signal(SignalKind::user_defined1()).context("failed to listen SIGUSR1")?;
+    let mut faster = signal(SignalKind::user_defined2()).context("failed to listen SIGUSR2")?;
+
+    tokio::spawn(async move {
+        while let Some(rate) = client.rate_limit() {
+            tokio::select! {
+                Some(()) = slower.recv() => client.set_rate_limit(Some((rate / 2).max(1))),
+                Some(()) = faster.recv() => client.set_rate_limit(Some(rate.saturating_mul(2))),
+                else => break,
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use hdl::{ErrorKind, MockTransport};
+
+    use super::*;
+
+    const GALLERY: &str = "https://nhentai.net/g/123456/";
+
+    fn image(page: usize) -> String {
+        format!("https://i3.nhentai.net/galleries/987654/{page}.jpg")
+    }
+
+    /// page saved in `tests/fixtures/`
+    fn fixture(path: &str) -> Vec<u8> {
+        std::fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(path),
+        )
+        .unwrap()
+    }
+
+    /// the nhentai gallery with its first images
+    fn nhentai() -> MockTransport {
+        (1..=3).fold(
+            MockTransport::new()
+                .page(GALLERY, fixture("nhentai/gallery.html"))
+                .page(
+                    "https://nhentai.net/g/123456/1/",
+                    fixture("nhentai/image.html"),
+                ),
+            |mock, page| mock.page(&image(page), format!("image {page}")),
+        )
+    }
+
+    /// config, history and downloads of a test, removed before and after it
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("hdl-main-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn history(&self) -> Arc<History> {
+            Arc::new(History::open(&self.0.join("history.jsonl")).unwrap())
+        }
+
+        /// downloads to `out/`, with `args` on the command line
+        fn session(&self, mock: &Arc<MockTransport>, args: &[&str]) -> Session {
+            let config = self.0.join("config.toml");
+            let out_dir = self.0.join("out");
+            std::fs::write(&config, format!("[global]\nout-dir = {:?}\n", out_dir)).unwrap();
+            let args = Args::try_parse_from(["hdl"].iter().chain(args).chain(&[GALLERY])).unwrap();
+            let settings = Settings::load(Some(&config), &args).unwrap();
+
+            Session {
+                client: Client::builder().transport(Arc::clone(mock) as _).build(),
+                settings: Arc::new(settings),
+                history: Some(self.history()),
+                dry_run: args.dry_run,
+                export_format: args.format,
+                force: args.force,
+                latest: args.latest,
+            }
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn job(url: &str, pages: &str) -> Job {
+        let pages = pages.parse::<PageRange>().unwrap();
+        Job {
+            url: url.to_owned(),
+            pages: pages.clone(),
+            selection: Some(pages),
+        }
+    }
+
+    fn record(status: history::Status) -> history::Record {
+        history::Record {
+            site: "Nhentai".to_owned(),
+            gallery_id: "123456".to_owned(),
+            url: GALLERY.to_owned(),
+            status,
+            out_dir: None,
+            pages: 24,
+            time: history::now(),
+            superseded_by: None,
+        }
+    }
+
+    async fn download(session: &Session, job: &Job) -> GalleryReport {
+        let mut report = GalleryReport::new(job.url.clone());
+        download_gallery(session, job, &mut report).await.unwrap();
+        report
+    }
+
+    #[tokio::test]
+    async fn skips_complete_galleries_unless_forced() {
+        let dir = TestDir::new("force");
+        dir.history()
+            .append(&record(history::Status::Complete))
+            .unwrap();
+
+        let mock = Arc::new(nhentai());
+        let report = download(&dir.session(&mock, &[]), &job(GALLERY, "1")).await;
+        assert!(report.skipped);
+        assert_eq!(report.pages, 24);
+        assert!(mock.requests().is_empty(), "{:?}", mock.requests());
+
+        let report = download(&dir.session(&mock, &["--force"]), &job(GALLERY, "1")).await;
+        assert!(!report.skipped);
+        assert!(matches!(report.outcome(), Outcome::Succeeded));
+        assert!(mock.requests().contains(&image(1)));
+        let out_dir = report.out_dir.unwrap();
+        assert_eq!(std::fs::read(out_dir.join("0001.jpg")).unwrap(), b"image 1");
+    }
+
+    #[tokio::test]
+    async fn resumes_partial_galleries() {
+        let dir = TestDir::new("resume");
+        dir.history()
+            .append(&record(history::Status::Partial))
+            .unwrap();
+
+        let mock = Arc::new(nhentai());
+        let report = download(&dir.session(&mock, &[]), &job(GALLERY, "1-2")).await;
+        assert!(!report.skipped);
+        assert_eq!(report.pages, 2);
+
+        // a page selection isn't the whole gallery
+        let latest = dir.history();
+        let latest = latest.get("Nhentai", "123456").unwrap();
+        assert_eq!(latest.status, history::Status::Partial);
+        assert_eq!(latest.pages, 2);
+    }
+
+    #[tokio::test]
+    async fn downloads_galleries_side_by_side_and_reports_them_in_order() {
+        let dir = TestDir::new("galleries");
+        let missing = "https://nhentai.net/g/111111/";
+        let other = "https://nhentai.net/g/222222/";
+        let delay = Duration::from_millis(300);
+        let mock = Arc::new(
+            nhentai()
+                .page(other, fixture("nhentai/gallery.html"))
+                .delay(GALLERY, delay)
+                .delay(missing, delay)
+                .delay(other, delay),
+        );
+        let session = dir.session(&mock, &["--galleries", "2"]);
+        let galleries = session.settings.galleries();
+        let jobs = vec![
+            job(GALLERY, "1"),
+            job(missing, "1"),
+            job("https://example.com/", "1"),
+            job(other, "2"),
+        ];
+
+        let start = tokio::time::Instant::now();
+        let reports = download_galleries(jobs, galleries, session).await.unwrap();
+        // two at a time: the last one waits for the missing gallery
+        assert!(start.elapsed() >= 2 * delay, "{:?}", start.elapsed());
+
+        let urls = reports
+            .iter()
+            .map(|(job, report)| (job.url.as_str(), report.url.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                (GALLERY, GALLERY),
+                (missing, missing),
+                ("https://example.com/", "https://example.com/"),
+                (other, other),
+            ]
+        );
+        let outcomes = reports
+            .iter()
+            .map(|(_, report)| report.outcome())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            outcomes[..],
+            [
+                Outcome::Succeeded,
+                Outcome::Failed,
+                Outcome::Failed,
+                Outcome::Succeeded
+            ]
+        ));
+        let errors = reports
+            .iter()
+            .map(|(_, report)| report.error.as_ref().map(ErrorKind::of))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                None,
+                Some(ErrorKind::NotFound),
+                Some(ErrorKind::Unsupported),
+                None
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn saves_what_an_interrupted_run_left() {
+        let dir = TestDir::new("interrupted");
+        let other = "https://nhentai.net/g/222222/";
+        let mock = Arc::new(nhentai().delay(GALLERY, Duration::from_millis(300)));
+        let session = dir.session(&mock, &[]);
+        let client = session.client.clone();
+        let path = dir.0.join("resume.json");
+
+        let finished = download_galleries(vec![job(GALLERY, "3")], 1, session.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            save_resume_state(&client, Some(path.clone()), &finished).unwrap(),
+            None
+        );
+        assert!(!path.exists());
+
+        let jobs = vec![job(GALLERY, "1-2"), job(other, "1")];
+        let (results, ()) = tokio::join!(download_galleries(jobs, 1, session), async {
+            // while the first gallery waits for its page
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            client.cancel();
+        });
+        let results = results.unwrap();
+        assert_eq!(
+            save_resume_state(&client, Some(path.clone()), &results).unwrap(),
+            Some(path.clone())
+        );
+
+        let left = failed::read(&path)
+            .unwrap()
+            .into_iter()
+            .map(|job| (job.url, job.pages.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            left,
+            [
+                (GALLERY.to_owned(), "1-2".to_owned()),
+                (other.to_owned(), "1".to_owned())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_json_progress_to_the_given_descriptor() {
+        use std::os::fd::AsRawFd;
+
+        let dir = TestDir::new("progress-fd");
+        let path = dir.0.join("progress.jsonl");
+        let file = std::fs::File::create(&path).unwrap();
+        let fd = file.as_raw_fd().to_string();
+
+        // stdout is taken by the dry run
+        let args = ["hdl", "--dry-run", "--progress", "json", GALLERY];
+        let args = Args::try_parse_from(args).unwrap();
+        assert!(json_output(&args).is_err());
+
+        let args = [
+            "hdl",
+            "--dry-run",
+            "--progress",
+            "json",
+            "--progress-fd",
+            &fd,
+        ];
+        let args = Args::try_parse_from(args.iter().chain(&[GALLERY])).unwrap();
+        let out = json_output(&args).unwrap();
+
+        let client = cli::test_run::client();
+        let (quit, quit_rx) = oneshot::channel();
+        let progress = tokio::spawn(json_progress(client.subscribe(), out, quit_rx));
+        cli::test_run::run(&client, &dir.0.join("out")).await;
+        quit.send(()).unwrap();
+        progress.await.unwrap().unwrap();
+        drop(file);
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        let events = lines
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let finished = events
+            .iter()
+            .filter(|line| line["event"] == "gallery_finished")
+            .count();
+        assert_eq!(finished, 3);
+        let run = events.last().unwrap();
+        assert_eq!(run["event"], "run_finished", "{lines}");
+        assert_eq!(run["galleries"], 3);
+        assert_eq!(run["failed"], 1);
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// 1-based page selection, e.g. `1-10,15,20-`
+#[derive(Clone, Debug, Default)]
+pub struct PageRange {
+    /// inclusive bounds, `None` end means "till the last page";
+    /// empty list selects every page
+    ranges: Vec<(usize, Option<usize>)>,
+}
+
+impl PageRange {
+    pub fn is_all(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, page: usize) -> bool {
+        self.ranges.is_empty()
+            || self
+                .ranges
+                .iter()
+                .any(|&(start, end)| start <= page && end.is_none_or(|end| page <= end))
+    }
+
+    /// `true` when neither `page` nor anything after it is selected
+    pub fn is_past(&self, page: usize) -> bool {
+        let mut last = 0;
+        for &(_, end) in &self.ranges {
+            match end {
+                Some(end) => last = last.max(end),
+                None => return false,
+            }
+        }
+
+        !self.ranges.is_empty() && page > last
+    }
+}
+
+impl FromStr for PageRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |num: &str| -> Result<usize, String> {
+            match num.trim().parse() {
+                Ok(0) => Err("pages are numbered from 1".to_owned()),
+                Ok(num) => Ok(num),
+                Err(e) => Err(format!("invalid page number {num:?}: {e}")),
+            }
+        };
+
+        let ranges = s
+            .split(',')
+            .map(|range| match range.split_once('-') {
+                Some((start, end)) => {
+                    let start = if start.trim().is_empty() {
+                        1
+                    } else {
+                        parse(start)?
+                    };
+                    let end = if end.trim().is_empty() {
+                        None
+                    } else {
+                        Some(parse(end)?)
+                    };
+
+                    match end {
+                        Some(end) if end < start => Err(format!("empty page range: {range:?}")),
+                        _ => Ok((start, end)),
+                    }
+                }
+                None => parse(range).map(|page| (page, Some(page))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { ranges })
+    }
+}
+
+/// single pages, an empty iterator selects every page
+impl FromIterator<usize> for PageRange {
+    fn from_iter<I: IntoIterator<Item = usize>>(pages: I) -> Self {
+        Self {
+            ranges: pages.into_iter().map(|page| (page, Some(page))).collect(),
+        }
+    }
+}
+
+/// same syntax as [`FromStr`], empty for every page
+impl fmt::Display for PageRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, &(start, end)) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            match end {
+                Some(end) if end == start => write!(f, "{start}")?,
+                Some(end) => write!(f, "{start}-{end}")?,
+                None => write!(f, "{start}-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pages(range: &PageRange, upto: usize) -> Vec<usize> {
+        (1..=upto).filter(|&page| range.contains(page)).collect()
+    }
+
+    #[test]
+    fn parses_ranges() {
+        let range = "1-3,5, 8-".parse::<PageRange>().unwrap();
+        assert_eq!(pages(&range, 10), [1, 2, 3, 5, 8, 9, 10]);
+        assert!(!range.is_all());
+
+        let range = "1-10,15,20-".parse::<PageRange>().unwrap();
+        assert!(range.contains(10) && range.contains(15) && range.contains(200));
+        assert!(!range.contains(11) && !range.contains(19));
+
+        let start = "-3".parse::<PageRange>().unwrap();
+        assert_eq!(pages(&start, 10), [1, 2, 3]);
+
+        assert!(PageRange::default().is_all());
+        assert_eq!(pages(&PageRange::default(), 3), [1, 2, 3]);
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        for invalid in ["5-3", "0", "0-4", "3-0", "", "1,,2", "a-b", "1-2-3"] {
+            assert!(invalid.parse::<PageRange>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn knows_when_past_the_selection() {
+        let range = "2-4,7".parse::<PageRange>().unwrap();
+        assert!(!range.is_past(7));
+        assert!(range.is_past(8));
+
+        let open = "2-4,10-".parse::<PageRange>().unwrap();
+        assert!(!open.is_past(5));
+        assert!(!open.is_past(1000));
+
+        assert!(!PageRange::default().is_past(1000));
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        for range in ["1-10,15,20-", "1-3", "4", "2,4,6-"] {
+            let parsed = range.parse::<PageRange>().unwrap();
+            assert_eq!(parsed.to_string(), range);
+            let again = parsed.to_string().parse::<PageRange>().unwrap();
+            assert_eq!(pages(&again, 30), pages(&parsed, 30));
+        }
+        assert_eq!("-3".parse::<PageRange>().unwrap().to_string(), "1-3");
+        assert_eq!([3, 5].into_iter().collect::<PageRange>().to_string(), "3,5");
+        assert_eq!(PageRange::default().to_string(), "");
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// token bucket shared by all download tasks
+pub(crate) struct RateLimiter {
+    /// bytes per second, `0` means unlimited
+    rate: AtomicU64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// can be negative: consumers take what they need and sleep off the debt
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: u64) -> Self {
+        Self {
+            rate: AtomicU64::new(rate),
+            bucket: Mutex::new(Bucket {
+                tokens: rate as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub(crate) fn rate(&self) -> Option<u64> {
+        match self.rate.load(Ordering::Relaxed) {
+            0 => None,
+            rate => Some(rate),
+        }
+    }
+
+    pub(crate) fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// wait until `len` bytes may be passed through
+    pub(crate) async fn acquire(&self, len: usize) {
+        let Some(rate) = self.rate() else {
+            return;
+        };
+        let rate = rate as f64;
+
+        let wait = {
+            let mut bucket = self.bucket.lock().await;
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.last_refill = now;
+            // allow bursts of up to one second worth of data
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+            bucket.tokens -= len as f64;
+
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        };
+
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_off_the_debt() {
+        let limiter = RateLimiter::new(1000);
+
+        // one second worth of data passes at once
+        let start = Instant::now();
+        limiter.acquire(1000).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        let start = Instant::now();
+        limiter.acquire(500).await;
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(450), "{waited:?}");
+        assert!(waited < Duration::from_millis(1000), "{waited:?}");
+    }
+
+    #[tokio::test]
+    async fn unlimited_never_waits() {
+        let limiter = RateLimiter::new(0);
+        assert_eq!(limiter.rate(), None);
+
+        let start = Instant::now();
+        limiter.acquire(usize::MAX).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        limiter.set_rate(10);
+        assert_eq!(limiter.rate(), Some(10));
+    }
+}
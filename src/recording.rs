@@ -0,0 +1,452 @@
+//! sessions recorded as one json line per exchange, and replayed from them
+//!
+//! ```json
+//! {"url":"https://nhentai.net/g/123/","status":200,"headers":[["content-type","text/html"]],"body":"<html>..."}
+//! {"url":"https://i3.nhentai.net/galleries/456/1.jpg","status":200,"truncated":183042}
+//! {"url":"https://i3.nhentai.net/galleries/456/2.jpg","error":"connection reset"}
+//! {"url":"https://api.e-hentai.org/api.php","method":"POST","request":"{...}","status":200,"body":"{...}"}
+//! ```
+//!
+//! Text bodies are kept as `body`, others as `body_base64`. Image bodies are
+//! dropped unless [`RecordingTransport::full_images`] is set, `truncated` is
+//! their size.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context as TaskContext, Poll};
+
+use anyhow::{bail, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::body::{Frame, SizeHint};
+use hyper::{Response, StatusCode, Uri};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorKind};
+use crate::site_options::SiteOptions;
+use crate::transport::{Body, MockTransport, Transport};
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Exchange {
+    url: String,
+    /// `POST` of a site api, `GET` when missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    /// body of a `POST`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Exchange {
+    fn set_body(&mut self, body: Vec<u8>) {
+        match String::from_utf8(body) {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => self.body = Some(text),
+            Err(e) => self.body_base64 = Some(BASE64_STANDARD.encode(e.as_bytes())),
+        }
+    }
+
+    fn body(&self) -> Result<Bytes> {
+        Ok(match (&self.body, &self.body_base64) {
+            (Some(text), _) => Bytes::from(text.clone()),
+            (None, Some(base64)) => BASE64_STANDARD
+                .decode(base64)
+                .context("invalid body_base64")?
+                .into(),
+            (None, None) => Bytes::new(),
+        })
+    }
+}
+
+type Sink = Mutex<Box<dyn Write + Send>>;
+
+fn write(sink: &Sink, exchange: &Exchange) {
+    let res = serde_json::to_string(exchange)
+        .map_err(anyhow::Error::from)
+        .and_then(|line| {
+            let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+            writeln!(sink, "{line}")?;
+            // a run can be killed at any time, keep what was recorded so far
+            sink.flush()?;
+            Ok(())
+        });
+    if let Err(e) = res {
+        tracing::warn!(
+            url = exchange.url,
+            error = format!("{e:#}"),
+            "failed to record"
+        );
+    }
+}
+
+/// records the exchanges of another transport for [`ReplayTransport`]
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    sink: Arc<Sink>,
+    full_images: bool,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn Transport>, out: impl Write + Send + 'static) -> Self {
+        Self {
+            inner,
+            sink: Arc::new(Mutex::new(Box::new(out))),
+            full_images: false,
+        }
+    }
+
+    /// records to a new file at `path`
+    pub fn create(inner: Arc<dyn Transport>, path: &Path) -> Result<Self, Error> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create recording: {path:?}"))
+            .map_err(Error::from_anyhow)?;
+        Ok(Self::new(inner, BufWriter::new(file)))
+    }
+
+    /// keep image bodies instead of only their size
+    pub fn full_images(mut self, full_images: bool) -> Self {
+        self.full_images = full_images;
+        self
+    }
+}
+
+impl RecordingTransport {
+    fn record(
+        &self,
+        mut exchange: Exchange,
+        response: Result<Response<Body>, Error>,
+    ) -> Result<Response<Body>, Error> {
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                exchange.error = Some(format!("{e:#}"));
+                write(&self.sink, &exchange);
+                return Err(e);
+            }
+        };
+
+        let (parts, body) = response.into_parts();
+        exchange.status = Some(parts.status.as_u16());
+        exchange.headers = parts
+            .headers
+            .iter()
+            // recordings end up in bug reports
+            .filter(|(name, _)| *name != hyper::header::SET_COOKIE)
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), value)
+            })
+            .collect();
+        let image = parts
+            .headers
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("image/"));
+
+        let body = Tee {
+            inner: body,
+            body: Vec::new(),
+            size: 0,
+            keep: self.full_images || !image,
+            ended: false,
+            exchange,
+            sink: Arc::clone(&self.sink),
+        };
+        Ok(Response::from_parts(parts, body.boxed()))
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for RecordingTransport {
+    async fn get(&self, url: &Uri, options: &SiteOptions) -> Result<Response<Body>, Error> {
+        let exchange = Exchange {
+            url: url.to_string(),
+            ..Exchange::default()
+        };
+        self.record(exchange, self.inner.get(url, options).await)
+    }
+
+    async fn post(
+        &self,
+        url: &Uri,
+        content_type: &str,
+        body: Bytes,
+        options: &SiteOptions,
+    ) -> Result<Response<Body>, Error> {
+        let exchange = Exchange {
+            url: url.to_string(),
+            method: Some("POST".to_owned()),
+            request: Some(String::from_utf8_lossy(&body).into_owned()),
+            ..Exchange::default()
+        };
+        let response = self.inner.post(url, content_type, body, options).await;
+        self.record(exchange, response)
+    }
+}
+
+/// passes the body through and records it once dropped
+struct Tee {
+    inner: Body,
+    body: Vec<u8>,
+    size: u64,
+    keep: bool,
+    ended: bool,
+    exchange: Exchange,
+    sink: Arc<Sink>,
+}
+
+impl hyper::body::Body for Tee {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+        let this = &mut *self;
+        let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    this.size += data.len() as u64;
+                    if this.keep {
+                        this.body.extend_from_slice(data);
+                    }
+                }
+            }
+            Some(Err(e)) => this.exchange.error = Some(format!("{e:#}")),
+            None => this.ended = true,
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for Tee {
+    fn drop(&mut self) {
+        // bodies of error statuses are often never read, that isn't a failure
+        if !self.ended && self.size > 0 && self.exchange.error.is_none() {
+            self.exchange.error = Some("body not read to the end".to_owned());
+        }
+        if self.keep {
+            self.exchange.set_body(std::mem::take(&mut self.body));
+        } else {
+            self.exchange.truncated = Some(self.size);
+        }
+        write(&self.sink, &self.exchange);
+    }
+}
+
+/// serves the exchanges of a [`RecordingTransport`] again
+///
+/// Exchanges of a url are given in the recorded order, the last one is
+/// repeated. Urls missing from the recording fail.
+pub struct ReplayTransport {
+    mock: MockTransport,
+    urls: HashSet<String>,
+}
+
+impl ReplayTransport {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        File::open(path)
+            .with_context(|| format!("failed to open recording: {path:?}"))
+            .and_then(|file| {
+                Self::read(BufReader::new(file))
+                    .with_context(|| format!("invalid recording: {path:?}"))
+            })
+            .map_err(Error::from_anyhow)
+    }
+
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, Error> {
+        Self::read(reader).map_err(Error::from_anyhow)
+    }
+
+    fn read(reader: impl BufRead) -> Result<Self> {
+        let mut mock = MockTransport::new();
+        let mut urls = HashSet::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange = serde_json::from_str::<Exchange>(&line)
+                .with_context(|| format!("line {}", index + 1))?;
+
+            mock = match (&exchange.error, exchange.status) {
+                (Some(error), _) => mock.error(&exchange.url, error),
+                (None, Some(status)) => {
+                    let status = StatusCode::from_u16(status)
+                        .with_context(|| format!("line {}: invalid status", index + 1))?;
+                    let body = exchange
+                        .body()
+                        .with_context(|| format!("line {}", index + 1))?;
+                    mock.response(&exchange.url, status, body)
+                }
+                (None, None) => bail!("line {}: neither status nor error", index + 1),
+            };
+            urls.insert(exchange.url);
+        }
+
+        Ok(Self { mock, urls })
+    }
+}
+
+impl ReplayTransport {
+    fn check(&self, url: &Uri) -> Result<(), Error> {
+        if !self.urls.contains(&url.to_string()) {
+            let message = format!("{url} is not in the recording");
+            return Err(Error::msg(ErrorKind::Other, message));
+        }
+        Ok(())
+    }
+}
+
+/// `POST` requests are answered by url, like `GET` ones
+#[async_trait::async_trait]
+impl Transport for ReplayTransport {
+    async fn get(&self, url: &Uri, options: &SiteOptions) -> Result<Response<Body>, Error> {
+        self.check(url)?;
+        self.mock.get(url, options).await
+    }
+
+    async fn post(
+        &self,
+        url: &Uri,
+        content_type: &str,
+        body: Bytes,
+        options: &SiteOptions,
+    ) -> Result<Response<Body>, Error> {
+        self.check(url)?;
+        self.mock.post(url, content_type, body, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::downloaders::CollectResponse;
+
+    use super::*;
+
+    /// shared with the recorder, read after it's done
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct ImageTransport;
+
+    #[async_trait::async_trait]
+    impl Transport for ImageTransport {
+        async fn get(&self, _url: &Uri, _options: &SiteOptions) -> Result<Response<Body>, Error> {
+            let body = http_body_util::Full::new(Bytes::from_static(b"\x89PNG\r\n\x1a\n"))
+                .map_err(|never| match never {})
+                .boxed();
+            Ok(Response::builder()
+                .header(hyper::header::CONTENT_TYPE, "image/png")
+                .header(hyper::header::SET_COOKIE, "session=secret")
+                .body(body)
+                .unwrap())
+        }
+    }
+
+    async fn get(transport: &dyn Transport, url: &str) -> Result<(StatusCode, Vec<u8>)> {
+        let response = transport.get(&url.parse()?, &SiteOptions::new()).await?;
+        let status = response.status();
+        Ok((status, response.collect_response().await?))
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_exchanges() {
+        let mock = MockTransport::new()
+            .page("https://example.com/text", "<html>hi</html>")
+            .page("https://example.com/binary", vec![0xff, 0xfe, 0])
+            .response("https://example.com/missing", StatusCode::NOT_FOUND, "gone")
+            .error("https://example.com/retry", "connection reset")
+            .page("https://example.com/retry", "second try");
+        let out = Buffer::default();
+        let recorder = RecordingTransport::new(Arc::new(mock), out.clone());
+
+        for url in ["text", "binary", "missing", "retry", "retry"] {
+            let _ = get(&recorder, &format!("https://example.com/{url}")).await;
+        }
+        drop(recorder);
+
+        let recording = out.0.lock().unwrap().clone();
+        assert_eq!(recording.iter().filter(|b| **b == b'\n').count(), 5);
+        let replay = ReplayTransport::from_reader(recording.as_slice()).unwrap();
+
+        let text = get(&replay, "https://example.com/text").await.unwrap();
+        assert_eq!(text, (StatusCode::OK, b"<html>hi</html>".to_vec()));
+        let binary = get(&replay, "https://example.com/binary").await.unwrap();
+        assert_eq!(binary, (StatusCode::OK, vec![0xff, 0xfe, 0]));
+        let missing = get(&replay, "https://example.com/missing").await.unwrap();
+        assert_eq!(missing, (StatusCode::NOT_FOUND, b"gone".to_vec()));
+        let error = get(&replay, "https://example.com/retry").await.unwrap_err();
+        assert_eq!(error.to_string(), "connection reset");
+        let retry = get(&replay, "https://example.com/retry").await.unwrap();
+        assert_eq!(retry.1, b"second try");
+
+        let unknown = get(&replay, "https://example.com/other").await.unwrap_err();
+        assert!(unknown.to_string().contains("not in the recording"));
+    }
+
+    #[tokio::test]
+    async fn truncates_images() {
+        for full_images in [false, true] {
+            let out = Buffer::default();
+            let recorder = RecordingTransport::new(Arc::new(ImageTransport), out.clone())
+                .full_images(full_images);
+            let (_, body) = get(&recorder, "https://example.com/1.png").await.unwrap();
+            assert_eq!(body, b"\x89PNG\r\n\x1a\n");
+
+            let line = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+            let exchange = serde_json::from_str::<Exchange>(&line).unwrap();
+            assert!(!line.contains("secret"));
+            assert_eq!(
+                exchange.headers,
+                [("content-type".into(), "image/png".into())]
+            );
+            if full_images {
+                assert_eq!(exchange.body().unwrap(), body);
+                assert_eq!(exchange.truncated, None);
+            } else {
+                assert!(exchange.body().unwrap().is_empty());
+                assert_eq!(exchange.truncated, Some(8));
+            }
+        }
+    }
+}
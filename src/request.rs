@@ -3,12 +3,13 @@ use std::sync::{Arc, OnceLock};
 use anyhow::{bail, Context, Result};
 
 use bytes::Bytes;
-use http_body_util::Empty;
-use hyper::{Request, Response};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::{Request, Response, Uri};
 use hyper_util::rt::TokioIo;
 
 use tokio::{
-    io::{AsyncRead, AsyncWrite},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
 };
 use tokio_rustls::{
@@ -16,20 +17,55 @@ use tokio_rustls::{
     rustls::{self, pki_types::ServerName},
     TlsConnector,
 };
+use tracing::Instrument;
+
+use crate::error::{Error, ErrorKind};
+use crate::site_options::SiteOptions;
+use crate::transport::{Body, Transport};
 
 trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
 impl Stream for TcpStream {}
 impl Stream for TlsStream<TcpStream> {}
 
+/// requests over the network with hyper and rustls, one connection per request
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HttpTransport;
+
+#[async_trait::async_trait]
+impl Transport for HttpTransport {
+    async fn get(&self, url: &Uri, options: &SiteOptions) -> Result<Response<Body>, Error> {
+        request(url, None, options)
+            .await
+            .map(boxed)
+            .map_err(Error::from_anyhow)
+    }
+
+    async fn post(
+        &self,
+        url: &Uri,
+        content_type: &str,
+        body: Bytes,
+        options: &SiteOptions,
+    ) -> Result<Response<Body>, Error> {
+        request(url, Some((content_type, body)), options)
+            .await
+            .map(boxed)
+            .map_err(Error::from_anyhow)
+    }
+}
+
+fn boxed(response: Response<Incoming>) -> Response<Body> {
+    response.map(|body| body.map_err(|e| Error::new(ErrorKind::Network, e)).boxed())
+}
+
 static TLS_CONFIG: OnceLock<Arc<rustls::ClientConfig>> = OnceLock::new();
 
 #[inline]
 fn tls_config() -> Arc<rustls::ClientConfig> {
     TLS_CONFIG
         .get_or_init(|| {
-            rustls::crypto::ring::default_provider()
-                .install_default()
-                .expect("failed to instal default crypto provider");
+            // fails only if the application already installed its own provider
+            let _ = rustls::crypto::ring::default_provider().install_default();
 
             let roots = rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.to_owned());
             let tls = rustls::ClientConfig::builder()
@@ -40,7 +76,12 @@ fn tls_config() -> Arc<rustls::ClientConfig> {
         .clone()
 }
 
-pub(crate) async fn request(url: &hyper::Uri) -> Result<Response<hyper::body::Incoming>> {
+/// `GET url`, or `POST` of `body` with its content type
+async fn request(
+    url: &Uri,
+    body: Option<(&str, Bytes)>,
+    options: &SiteOptions,
+) -> Result<Response<Incoming>> {
     enum Protocol {
         Http,
         Https,
@@ -61,15 +102,39 @@ pub(crate) async fn request(url: &hyper::Uri) -> Result<Response<hyper::body::In
     });
     let addr = format!("{host}:{port}");
 
-    let tcp_stream = TcpStream::connect(&addr)
-        .await
-        .with_context(|| format!("failed to connect to: {addr}"))?;
+    let tcp_stream = match &options.proxy {
+        Some(proxy) => {
+            let proxy_addr = format!(
+                "{}:{}",
+                proxy.host().context("no host in proxy url")?,
+                proxy.port_u16().unwrap_or(80)
+            );
+            let mut stream = TcpStream::connect(&proxy_addr)
+                .await
+                .with_context(|| format!("failed to connect to proxy: {proxy_addr}"))
+                .context(ErrorKind::Network)?;
+            if let Protocol::Https = protocol {
+                connect_tunnel(&mut stream, &addr)
+                    .await
+                    .context(ErrorKind::Network)?;
+            }
+            stream
+        }
+        None => TcpStream::connect(&addr)
+            .await
+            .with_context(|| format!("failed to connect to: {addr}"))
+            .context(ErrorKind::Network)?,
+    };
 
     let stream: Box<dyn Stream> = match protocol {
         Protocol::Https => {
             let domain = ServerName::try_from(host)?;
             let connector = TlsConnector::from(tls_config());
-            let tls_stream = connector.connect(domain, tcp_stream).await?;
+            let tls_stream = connector
+                .connect(domain, tcp_stream)
+                .await
+                .context("tls handshake failed")
+                .context(ErrorKind::Network)?;
             Box::new(tls_stream)
         }
         Protocol::Http => Box::new(tcp_stream),
@@ -77,23 +142,157 @@ pub(crate) async fn request(url: &hyper::Uri) -> Result<Response<hyper::body::In
 
     let io = TokioIo::new(stream);
 
-    let (mut sender, conn) = hyper::client::conn::http1::handshake(io).await?;
-    tokio::task::spawn(async move {
-        if let Err(err) = conn.await {
-            eprintln!("Connection failed: {:?}", err);
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(io)
+        .await
+        .context(ErrorKind::Network)?;
+    // connection errors also fail the pending request, so they are reported there
+    tokio::task::spawn(
+        async move {
+            if let Err(e) = conn.await {
+                tracing::debug!(error = %e, "connection closed");
+            }
         }
-    });
+        .in_current_span(),
+    );
 
     let authority = url.authority().context("failed to get authority")?.clone();
 
-    let req = Request::builder()
-        .uri(
-            url.path_and_query()
-                .map(|p| p.as_str())
-                .unwrap_or_else(|| url.path()),
-        )
-        .header(hyper::header::HOST, authority.as_str())
-        .body(Empty::<Bytes>::new())?;
+    // a plain http proxy wants the whole url
+    let target = match (&options.proxy, protocol) {
+        (Some(_), Protocol::Http) => url.to_string(),
+        _ => url
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or_else(|| url.path())
+            .to_owned(),
+    };
+
+    let mut req = Request::builder()
+        .uri(target)
+        .header(hyper::header::HOST, authority.as_str());
+    if let Some(cookies) = &options.cookies {
+        req = req.header(hyper::header::COOKIE, cookies);
+    }
+    for (name, value) in &options.headers {
+        req = req.header(name, value);
+    }
+    let req = match body {
+        Some((content_type, body)) => req
+            .method(hyper::Method::POST)
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(Full::new(body))?,
+        None => req.body(Full::new(Bytes::new()))?,
+    };
+
+    sender.send_request(req).await.context(ErrorKind::Network)
+}
+
+/// asks an http proxy for a tunnel to `addr`
+async fn connect_tunnel(stream: &mut TcpStream, addr: &str) -> Result<()> {
+    const MAX_RESPONSE_LEN: usize = 8 * 1024;
+
+    stream
+        .write_all(format!("CONNECT {addr} HTTP/1.1\r\nHost: {addr}\r\n\r\n").as_bytes())
+        .await?;
 
-    Ok(sender.send_request(req).await?)
+    // read byte by byte so nothing after the response head is consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > MAX_RESPONSE_LEN {
+            bail!("proxy response too long");
+        }
+        head.push(
+            stream
+                .read_u8()
+                .await
+                .context("proxy closed the connection")?,
+        );
+    }
+
+    let status_line = String::from_utf8_lossy(head.split(|&b| b == b'\r').next().unwrap_or(&[]));
+    if status_line.split(' ').nth(1) != Some("200") {
+        bail!("proxy refused to connect to {addr}: {status_line}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloaders::CollectResponse;
+    use crate::test_server::TestServer;
+
+    #[tokio::test]
+    async fn sends_site_cookies_and_headers() {
+        let server = TestServer::start(vec![("/page", 200, b"hello".to_vec())]).await;
+        let options = SiteOptions::new()
+            .cookies("session=1")
+            .header("Referer", "https://example.com/");
+        let url = Uri::try_from(server.url("/page")).unwrap();
+
+        let response = HttpTransport.get(&url, &options).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.collect_response().await.unwrap(), b"hello");
+
+        let head = server.requests()[0].to_lowercase();
+        assert!(head.starts_with("get /page http/1.1\r\n"));
+        assert!(head.contains(&format!("host: {}\r\n", server.addr())));
+        assert!(head.contains("cookie: session=1\r\n"));
+        assert!(head.contains("referer: https://example.com/\r\n"));
+    }
+
+    #[tokio::test]
+    async fn posts_bodies() {
+        let server = TestServer::start(vec![("/api", 200, b"{}".to_vec())]).await;
+        let url = Uri::try_from(server.url("/api")).unwrap();
+
+        let response = HttpTransport
+            .post(
+                &url,
+                "application/json",
+                Bytes::from_static(b"{\"method\":\"gdata\"}"),
+                &SiteOptions::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.collect_response().await.unwrap(), b"{}");
+
+        let request = server.requests()[0].to_lowercase();
+        assert!(request.starts_with("post /api http/1.1\r\n"));
+        assert!(request.contains("content-type: application/json\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"method\":\"gdata\"}"));
+    }
+
+    #[tokio::test]
+    async fn returns_error_statuses() {
+        let server = TestServer::start(Vec::new()).await;
+        let url = Uri::try_from(server.url("/missing")).unwrap();
+
+        let response = HttpTransport.get(&url, &SiteOptions::new()).await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn sends_whole_url_to_http_proxy() {
+        let proxy = TestServer::start(vec![("/page", 200, b"proxied".to_vec())]).await;
+        let options = SiteOptions::new().proxy(Uri::try_from(proxy.url("")).unwrap());
+        let url = Uri::from_static("http://gallery.invalid/page");
+
+        let response = HttpTransport.get(&url, &options).await.unwrap();
+        assert_eq!(response.collect_response().await.unwrap(), b"proxied");
+        assert!(proxy.requests()[0].starts_with("GET http://gallery.invalid/page HTTP/1.1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn fails_when_proxy_refuses_tunnel() {
+        let proxy = TestServer::start(Vec::new()).await;
+        let options = SiteOptions::new().proxy(Uri::try_from(proxy.url("")).unwrap());
+        let url = Uri::from_static("https://gallery.invalid/page");
+
+        let e = HttpTransport.get(&url, &options).await.unwrap_err();
+        assert!(format!("{e:#}").contains("proxy refused"), "{e:#}");
+        assert_eq!(e.kind(), &ErrorKind::Network);
+        assert!(proxy.requests()[0].starts_with("CONNECT gallery.invalid:443 HTTP/1.1\r\n"));
+    }
 }
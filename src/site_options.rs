@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use hyper::Uri;
+
+use crate::config;
+
+/// request and download settings, for every site with [`crate::ClientBuilder::defaults`]
+/// or for one of them with [`crate::ClientBuilder::site`]
+#[derive(Clone, Debug, Default)]
+pub struct SiteOptions {
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) cookies: Option<String>,
+    pub(crate) proxy: Option<Uri>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retries: Option<usize>,
+    pub(crate) dir_template: Option<String>,
+    pub(crate) rate_limit: Option<u64>,
+}
+
+impl SiteOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// sent with every request, replaces a header of the same name from the defaults
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// value of the `Cookie` header, e.g. `"name=value; other=value"`
+    pub fn cookies(mut self, cookies: impl Into<String>) -> Self {
+        self.cookies = Some(cookies.into());
+        self
+    }
+
+    /// http proxy, https urls are tunneled with `CONNECT`
+    pub fn proxy(mut self, proxy: Uri) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// longest wait for a response or the next chunk of it, 10 seconds by default;
+    /// zero keeps the default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout).filter(|timeout| !timeout.is_zero());
+        self
+    }
+
+    /// attempts per page, 3 by default
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = Some(retries.max(1));
+        self
+    }
+
+    /// gallery directory inside the output directory, `{title}` by default;
+    /// `{site}` and `{id}` are replaced too and `/` makes subdirectories
+    pub fn dir_template(mut self, template: impl Into<String>) -> Self {
+        self.dir_template = Some(template.into());
+        self
+    }
+
+    /// download rate of the site in bytes per second, shared by its galleries and
+    /// on top of [`crate::ClientBuilder::rate_limit`]; 0 is unlimited
+    pub fn rate_limit(mut self, rate: u64) -> Self {
+        self.rate_limit = Some(rate).filter(|&rate| rate > 0);
+        self
+    }
+
+    /// `site` settings win, headers are merged by name
+    pub(crate) fn merge(&self, site: &SiteOptions) -> SiteOptions {
+        let mut headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                !site
+                    .headers
+                    .iter()
+                    .any(|(site_name, _)| site_name.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        headers.extend(site.headers.iter().cloned());
+
+        SiteOptions {
+            headers,
+            cookies: site.cookies.clone().or_else(|| self.cookies.clone()),
+            proxy: site.proxy.clone().or_else(|| self.proxy.clone()),
+            timeout: site.timeout.or(self.timeout),
+            retries: site.retries.or(self.retries),
+            dir_template: site
+                .dir_template
+                .clone()
+                .or_else(|| self.dir_template.clone()),
+            rate_limit: site.rate_limit.or(self.rate_limit),
+        }
+    }
+
+    /// the timeout in effect, the default one if not set
+    pub fn read_timeout(&self) -> Duration {
+        self.timeout.unwrap_or(config::REQUEST_READ_TIMEOUT)
+    }
+
+    /// the attempts per page in effect
+    pub fn attempts(&self) -> usize {
+        self.retries.unwrap_or(config::DOWNLOAD_RETRIES)
+    }
+
+    /// the directory template in effect
+    pub fn template(&self) -> &str {
+        self.dir_template.as_deref().unwrap_or(config::DIR_TEMPLATE)
+    }
+}
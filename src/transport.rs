@@ -0,0 +1,344 @@
+//! how requests reach the sites: [`crate::HttpTransport`] over the network,
+//! [`MockTransport`] from memory for tests
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{self, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::body::Frame;
+use hyper::{Response, StatusCode, Uri};
+
+use crate::downloaders::CollectResponse;
+use crate::error::{self, Error, ErrorKind, Interstitial, SelectorNotFound};
+use crate::rate_limit::RateLimiter;
+use crate::site_options::SiteOptions;
+
+/// response body of a [`Transport`]
+pub type Body = BoxBody<Bytes, Error>;
+
+/// sends the requests of a [`crate::Client`], see [`crate::ClientBuilder::transport`]
+#[async_trait::async_trait]
+pub trait Transport: Send + Sync {
+    /// `GET url` with the headers, cookies and proxy of `options`;
+    /// failures should have [`ErrorKind::Network`] when retrying can help
+    async fn get(&self, url: &Uri, options: &SiteOptions) -> Result<Response<Body>, Error>;
+
+    /// `POST url` with `body` of `content_type`, for site apis
+    async fn post(
+        &self,
+        url: &Uri,
+        _content_type: &str,
+        _body: Bytes,
+        _options: &SiteOptions,
+    ) -> Result<Response<Body>, Error> {
+        Err(Error::msg(
+            ErrorKind::Other,
+            format!("POST {url} isn't supported by this transport"),
+        ))
+    }
+}
+
+/// what a gallery download needs to reach its site
+#[derive(Clone)]
+pub(crate) struct Ctx {
+    transport: Arc<dyn Transport>,
+    site: Arc<SiteOptions>,
+    name: &'static str,
+    /// where pages that fail to parse are saved
+    debug_dump: Option<Arc<Path>>,
+    /// the [`SiteOptions::rate_limit`] of the site
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl Ctx {
+    pub(crate) fn new(
+        transport: Arc<dyn Transport>,
+        name: &'static str,
+        site: SiteOptions,
+    ) -> Self {
+        Self {
+            transport,
+            site: Arc::new(site),
+            name,
+            debug_dump: None,
+            limiter: None,
+        }
+    }
+
+    pub(crate) fn debug_dump(mut self, dir: Option<Arc<Path>>) -> Self {
+        self.debug_dump = dir;
+        self
+    }
+
+    pub(crate) fn limiter(mut self, limiter: Option<Arc<RateLimiter>>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// wait until the site's rate limit lets `len` more bytes through
+    pub(crate) async fn acquire(&self, len: usize) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(len).await;
+        }
+    }
+
+    pub(crate) fn site(&self) -> &SiteOptions {
+        &self.site
+    }
+
+    pub(crate) async fn get(&self, url: &Uri) -> Result<Response<Body>> {
+        tracing::trace!(%url, "GET");
+        Ok(self.transport.get(url, &self.site).await?)
+    }
+
+    pub(crate) async fn post(
+        &self,
+        url: &Uri,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Response<Body>> {
+        tracing::trace!(%url, "POST");
+        Ok(self
+            .transport
+            .post(url, content_type, body, &self.site)
+            .await?)
+    }
+
+    /// whole body of `url`, fails with [`error::status_error`] unless the status is a success
+    pub(crate) async fn page(&self, url: &Uri) -> Result<Vec<u8>> {
+        let response = self.get(url).await?;
+        let status = response.status();
+        let page = response.collect_response().await?;
+        if !status.is_success() {
+            return Err(error::status_error(url, status, &page));
+        }
+
+        Ok(page)
+    }
+
+    /// `parse(page)`, failures are marked as [`Interstitial`] or [`ErrorKind::Parse`]
+    /// and the page is saved to the debug dump directory
+    pub(crate) fn parse<T>(
+        &self,
+        url: &Uri,
+        page: &[u8],
+        parse: impl FnOnce(&[u8]) -> Result<T>,
+    ) -> Result<T> {
+        let e = match parse(page) {
+            Ok(parsed) => return Ok(parsed),
+            Err(e) => match Interstitial::detect(page) {
+                Some(interstitial) => interstitial.attach(e),
+                None => {
+                    let selector = e
+                        .downcast_ref::<SelectorNotFound>()
+                        .map(|selector| selector.0.clone());
+                    e.context(ErrorKind::Parse { selector })
+                }
+            },
+        };
+
+        let Some(dir) = &self.debug_dump else {
+            return Err(e);
+        };
+        match dump(dir, self.name, url, page, &e) {
+            Ok(path) => Err(e.context(format!("page saved to {path:?}"))),
+            Err(dump_error) => {
+                tracing::warn!(error = format!("{dump_error:#}"), "failed to save the page");
+                Err(e)
+            }
+        }
+    }
+}
+
+/// `<site>-<unix ms>-<n>.html` with the page and `.txt` with the url and the error
+fn dump(dir: &Path, site: &str, url: &Uri, page: &[u8], e: &anyhow::Error) -> Result<PathBuf> {
+    static DUMPS: AtomicUsize = AtomicUsize::new(0);
+
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let n = DUMPS.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!("{}-{millis}-{n}.html", site.to_lowercase()));
+
+    std::fs::write(&path, page).with_context(|| format!("failed to write {path:?}"))?;
+    let report = format!("url: {url}\nsite: {site}\nerror: {e:#}\n");
+    let report_path = path.with_extension("txt");
+    std::fs::write(&report_path, report)
+        .with_context(|| format!("failed to write {report_path:?}"))?;
+
+    Ok(path)
+}
+
+/// canned responses by url; urls without one get `404 Not Found`
+///
+/// Responses of a url are given in order, the last one is repeated.
+#[derive(Default)]
+pub struct MockTransport {
+    responses: Mutex<HashMap<String, VecDeque<MockResponse>>>,
+    delays: Mutex<HashMap<String, Duration>>,
+    requests: Mutex<Vec<String>>,
+    bodies: Mutex<Vec<Bytes>>,
+}
+
+#[derive(Clone)]
+enum MockResponse {
+    Ok(StatusCode, Bytes),
+    Err(String),
+    Stalled(Bytes),
+}
+
+/// body that sends its data and then hangs
+struct StalledBody(Option<Bytes>);
+
+impl hyper::body::Body for StalledBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+        match self.0.take() {
+            Some(data) => Poll::Ready(Some(Ok(Frame::data(data)))),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `200 OK` with `body`
+    pub fn page(self, url: &str, body: impl Into<Bytes>) -> Self {
+        self.response(url, StatusCode::OK, body)
+    }
+
+    pub fn response(self, url: &str, status: StatusCode, body: impl Into<Bytes>) -> Self {
+        self.push(url, MockResponse::Ok(status, body.into()))
+    }
+
+    /// the request fails before any response, like a dropped connection
+    pub fn error(self, url: &str, error: impl Into<String>) -> Self {
+        self.push(url, MockResponse::Err(error.into()))
+    }
+
+    /// `200 OK` that sends `body` and then nothing more, like a server that hangs
+    /// halfway through; `Content-Length` announces twice as much
+    pub fn stall(self, url: &str, body: impl Into<Bytes>) -> Self {
+        self.push(url, MockResponse::Stalled(body.into()))
+    }
+
+    /// every response of `url` comes after `delay`, like from a slow server
+    pub fn delay(self, url: &str, delay: Duration) -> Self {
+        self.delays
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(url.to_owned(), delay);
+        self
+    }
+
+    fn push(self, url: &str, response: MockResponse) -> Self {
+        self.responses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(url.to_owned())
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    /// urls requested so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// bodies of the `POST` requests so far, in order
+    pub fn bodies(&self) -> Vec<Bytes> {
+        self.bodies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    async fn respond(&self, url: &Uri) -> Result<Response<Body>, Error> {
+        let url = url.to_string();
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(url.clone());
+
+        let delay = self
+            .delays
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&url)
+            .copied();
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        let (status, body, stalled) = {
+            let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+            let response = match responses.get_mut(&url) {
+                Some(queue) if queue.len() > 1 => queue.pop_front(),
+                Some(queue) => queue.front().cloned(),
+                None => None,
+            };
+            match response {
+                Some(MockResponse::Ok(status, body)) => (status, body, false),
+                Some(MockResponse::Stalled(body)) => (StatusCode::OK, body, true),
+                Some(MockResponse::Err(e)) => return Err(Error::msg(ErrorKind::Other, e)),
+                None => (StatusCode::NOT_FOUND, Bytes::new(), false),
+            }
+        };
+
+        let len = body.len();
+        let (len, body) = match stalled {
+            true => (2 * len, StalledBody(Some(body)).boxed()),
+            false => (len, Full::new(body).map_err(|never| match never {}).boxed()),
+        };
+        let mut response = Response::new(body);
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(hyper::header::CONTENT_LENGTH, len.into());
+
+        Ok(response)
+    }
+}
+
+/// `POST` requests are answered like `GET` ones, by url
+#[async_trait::async_trait]
+impl Transport for MockTransport {
+    async fn get(&self, url: &Uri, _options: &SiteOptions) -> Result<Response<Body>, Error> {
+        self.respond(url).await
+    }
+
+    async fn post(
+        &self,
+        url: &Uri,
+        _content_type: &str,
+        body: Bytes,
+        _options: &SiteOptions,
+    ) -> Result<Response<Body>, Error> {
+        self.bodies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(body);
+        self.respond(url).await
+    }
+}